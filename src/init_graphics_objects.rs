@@ -9,6 +9,8 @@ use crate::window_objects::logger_object::Logger;
 
 use crate::window_objects::window_object_center::HiddenManager;
 use crate::window_objects::sshclient_object::SSHClient;
+use crate::window_objects::sshclient_object::PROMPT_BOX_IDLE_TEXT;
+use crate::window_objects::sshclient_object::COMMAND_BOX_IDLE_TEXT;
 
 // Only Interactables 
 use crate::window_objects::window_object_center::OnlyInteractable;
@@ -17,6 +19,21 @@ use crate::window_objects::textbox_object::TextBox;
 
 // Any Button Implementations Go Here
 use crate::interactable_implementations::button_implementations::SSHConnect;
+use crate::interactable_implementations::button_implementations::SSHAgentConnect;
+use crate::interactable_implementations::button_implementations::TrustHostKey;
+use crate::interactable_implementations::button_implementations::RejectHostKey;
+use crate::interactable_implementations::button_implementations::AddLocalForward;
+use crate::interactable_implementations::button_implementations::AddRemoteForward;
+use crate::interactable_implementations::button_implementations::StopForward;
+use crate::interactable_implementations::button_implementations::ToggleSocksProxy;
+use crate::interactable_implementations::button_implementations::ReconnectSession;
+use crate::interactable_implementations::button_implementations::LogoutSession;
+use crate::interactable_implementations::button_implementations::CancelCommand;
+use crate::interactable_implementations::button_implementations::SwitchSession;
+use crate::interactable_implementations::button_implementations::PickKey;
+use crate::interactable_implementations::button_implementations::PasteKey;
+use crate::interactable_implementations::button_implementations::SaveToVault;
+use crate::interactable_implementations::button_implementations::LockVault;
 
 // Any Textbox Implementations Go Here
 use crate::interactable_implementations::textbox_implementation::DoNothing;
@@ -24,12 +41,14 @@ use crate::interactable_implementations::textbox_implementation::ExecuteCommand;
 use crate::interactable_implementations::textbox_implementation::DownloadFile;
 use crate::interactable_implementations::textbox_implementation::UploadFile;
 use crate::interactable_implementations::textbox_implementation::UploadDirectory;
+use crate::interactable_implementations::textbox_implementation::AnswerPrompt;
+use crate::interactable_implementations::textbox_implementation::UnlockVault;
 
 // Import all IDs 
 use crate::object_ids::*;
 
-// Buttons: range from 1 to 20
-// Corresponding Backgrounds: 101 to 120
+// Buttons and Textboxes: range from 1 to 49
+// Corresponding Backgrounds: 101 to 149
 pub fn init_graphics_objects_main(non_interactable_components: &mut BTreeMap<u32, NonInteractable>, only_interactable_components: &mut BTreeMap<u32, OnlyInteractable>, hidden_components: &mut BTreeMap<u32, HiddenManager>) {
     only_interactable_components.insert(HOSTNAME_BOX, OnlyInteractable::TextBox(
         TextBox::new(25.0, 25.0, 300.0, 50.0,
@@ -76,6 +95,21 @@ pub fn init_graphics_objects_main(non_interactable_components: &mut BTreeMap<u32
     ));
     non_interactable_components.insert(PASSWORD_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 150.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
     
+    only_interactable_components.insert(PROMPT_BOX, OnlyInteractable::TextBox(
+        TextBox::new(25.0, 90.0, 300.0, 50.0,
+            Color::new(0.9, 0.9, 0.9, 1.0),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            Color::new(0.7, 0.7, 0.7, 1.0),
+            PROMPT_BOX_IDLE_TEXT.to_string(),
+            Box::new(AnswerPrompt),
+            TextBlock::new(35.0, 120.0, Color::new(0.0, 0.0, 0.0, 1.0), String::new(), 20.0),
+            true,  // Password mode, changed to match each prompt
+            true,  // Enter clears text 
+            false  // Enter removes focus
+        )
+    ));
+    non_interactable_components.insert(PROMPT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 85.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
     only_interactable_components.insert(PUBLIC_KEY_BOX, OnlyInteractable::TextBox(
         TextBox::new(340.0, 90.0, 300.0, 50.0,
             Color::new(0.9, 0.9, 0.9, 1.0),
@@ -122,23 +156,94 @@ pub fn init_graphics_objects_main(non_interactable_components: &mut BTreeMap<u32
     non_interactable_components.insert(PASSPHRASE_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 215.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
 
 
+    only_interactable_components.insert(JUMP_BOX, OnlyInteractable::TextBox(
+        TextBox::new(25.0, 285.0, 300.0, 50.0,
+            Color::new(0.9, 0.9, 0.9, 1.0),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            Color::new(0.7, 0.7, 0.7, 1.0),
+            "Jump hosts (user@host,...)".to_string(),
+            Box::new(DoNothing),
+            TextBlock::new(35.0, 315.0, Color::new(0.0, 0.0, 0.0, 1.0), String::new(), 20.0),
+            false,
+            false,
+            true
+        )
+    ));
+    non_interactable_components.insert(JUMP_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 280.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
     only_interactable_components.insert(LOGIN_BUTTON, OnlyInteractable::Button(
-        Button::new(182.0, 285.0, 300.0, 50.0,
+        Button::new(340.0, 285.0, 300.0, 50.0,
             Color::new(0.5, 0.2, 0.2, 1.0),
             Color::new(0.8, 0.5, 0.5, 1.0),
             Color::new(0.3, 0.01, 0.01, 1.0),
             Box::new(SSHConnect),
-            TextBlock::new(227.0, 315.0, Color::new(1.0, 1.0, 1.0, 1.0), "Login".to_string(), 20.0)
+            TextBlock::new(385.0, 315.0, Color::new(1.0, 1.0, 1.0, 1.0), "Login".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(LOGIN_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 280.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(AGENT_LOGIN_BUTTON, OnlyInteractable::Button(
+        Button::new(25.0, 220.0, 300.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(SSHAgentConnect),
+            TextBlock::new(70.0, 250.0, Color::new(1.0, 1.0, 1.0, 1.0), "Login with ssh-agent".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(AGENT_LOGIN_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 215.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    // Between the login panel and the rest, like the tab strip
+    only_interactable_components.insert(PICK_KEY_BUTTON, OnlyInteractable::Button(
+        Button::new(25.0, 357.0, 300.0, 34.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(PickKey),
+            TextBlock::new(70.0, 380.0, Color::new(1.0, 1.0, 1.0, 1.0), "Next key from ~/.ssh".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(PICK_KEY_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 352.0, 310.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(PASTE_KEY_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 357.0, 300.0, 34.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(PasteKey),
+            TextBlock::new(385.0, 380.0, Color::new(1.0, 1.0, 1.0, 1.0), "Paste private key".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(PASTE_KEY_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 352.0, 310.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(TRUST_HOST_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 415.0, 145.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(TrustHostKey),
+            TextBlock::new(355.0, 445.0, Color::new(1.0, 1.0, 1.0, 1.0), "Trust Host".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(TRUST_HOST_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 410.0, 155.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(REJECT_HOST_BUTTON, OnlyInteractable::Button(
+        Button::new(495.0, 415.0, 145.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(RejectHostKey),
+            TextBlock::new(510.0, 445.0, Color::new(1.0, 1.0, 1.0, 1.0), "Reject Host".to_string(), 20.0)
         )
     ));
-    non_interactable_components.insert(LOGIN_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(177.0, 280.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+    non_interactable_components.insert(REJECT_HOST_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(490.0, 410.0, 155.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
 
     only_interactable_components.insert(COMMAND_BOX, OnlyInteractable::TextBox(
         TextBox::new(25.0, 415.0, 300.0, 50.0,
             Color::new(0.9, 0.9, 0.9, 1.0),
             Color::new(1.0, 1.0, 1.0, 1.0),
             Color::new(0.7, 0.7, 0.7, 1.0),
-            "Enter Command".to_string(),
+            COMMAND_BOX_IDLE_TEXT.to_string(),
             Box::new(ExecuteCommand),
             TextBlock::new(35.0, 445.0, Color::new(0.0, 0.0, 0.0, 1.0), String::new(), 20.0),
             false,
@@ -194,6 +299,101 @@ pub fn init_graphics_objects_main(non_interactable_components: &mut BTreeMap<u32
     ));
     non_interactable_components.insert(UPLOAD_DIR_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 605.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
 
+    only_interactable_components.insert(FORWARD_BOX, OnlyInteractable::TextBox(
+        TextBox::new(25.0, 675.0, 300.0, 50.0,
+            Color::new(0.9, 0.9, 0.9, 1.0),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            Color::new(0.7, 0.7, 0.7, 1.0),
+            "port:host:hostport".to_string(),
+            Box::new(DoNothing),
+            TextBlock::new(35.0, 705.0, Color::new(0.0, 0.0, 0.0, 1.0), String::new(), 20.0),
+            false,
+            false,
+            true
+        )
+    ));
+    non_interactable_components.insert(FORWARD_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 670.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(ADD_FORWARD_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 675.0, 95.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(AddLocalForward),
+            TextBlock::new(355.0, 705.0, Color::new(1.0, 1.0, 1.0, 1.0), "Add -L".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(ADD_FORWARD_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 670.0, 105.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(ADD_REMOTE_FORWARD_BUTTON, OnlyInteractable::Button(
+        Button::new(443.0, 675.0, 95.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(AddRemoteForward),
+            TextBlock::new(458.0, 705.0, Color::new(1.0, 1.0, 1.0, 1.0), "Add -R".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(ADD_REMOTE_FORWARD_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(438.0, 670.0, 105.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(STOP_FORWARD_BUTTON, OnlyInteractable::Button(
+        Button::new(545.0, 675.0, 95.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(StopForward),
+            TextBlock::new(560.0, 705.0, Color::new(1.0, 1.0, 1.0, 1.0), "Stop".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(STOP_FORWARD_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(540.0, 670.0, 105.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(RECONNECT_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 480.0, 145.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(ReconnectSession),
+            TextBlock::new(355.0, 510.0, Color::new(1.0, 1.0, 1.0, 1.0), "Reconnect".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(RECONNECT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 475.0, 155.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(LOGOUT_BUTTON, OnlyInteractable::Button(
+        Button::new(495.0, 480.0, 145.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(LogoutSession),
+            TextBlock::new(510.0, 510.0, Color::new(1.0, 1.0, 1.0, 1.0), "Logout".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(LOGOUT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(490.0, 475.0, 155.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(CANCEL_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 545.0, 300.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(CancelCommand),
+            TextBlock::new(355.0, 575.0, Color::new(1.0, 1.0, 1.0, 1.0), "Cancel command (Ctrl+C)".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(CANCEL_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 540.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(SOCKS_BUTTON, OnlyInteractable::Button(
+        Button::new(340.0, 610.0, 300.0, 50.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(ToggleSocksProxy),
+            TextBlock::new(355.0, 640.0, Color::new(1.0, 1.0, 1.0, 1.0), "Start/Stop SOCKS proxy".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(SOCKS_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 605.0, 310.0, 60.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    non_interactable_components.insert(56, NonInteractable::ScreenDecoration(ScreenDecoration::new(20.0, 740.0, 625.0, 140.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+    non_interactable_components.insert(TUNNEL_LIST, NonInteractable::Logger(Logger::new(20.0, 740.0, 625.0, 140.0, 5.0, 20.0, Color::new(1.0, 1.0, 1.0, 1.0), "".to_string())));
+
     non_interactable_components.insert(51,  NonInteractable::ScreenDecoration(ScreenDecoration::new(9.0, 9.0, 664.0, 342.0, Color::new(0.2, 0.2, 0.2, 1.0))));
     non_interactable_components.insert(52, NonInteractable::ScreenDecoration(ScreenDecoration::new(9.0, 399.0, 664.0, 492.0, Color::new(0.2, 0.2, 0.2, 1.0))));
 
@@ -201,15 +401,61 @@ pub fn init_graphics_objects_main(non_interactable_components: &mut BTreeMap<u32
     
     non_interactable_components.insert(LOGGER, NonInteractable::Logger(Logger::new(682.0, 50.0, 1080.0, 840.0, 5.0, 20.0, Color::new(1.0, 1.0, 1.0, 1.0), "".to_string())));
 
-    non_interactable_components.insert(71, NonInteractable::TextBlock(TextBlock::new(1161.0, 33.0, Color::new(0.05, 0.05, 0.05, 1.0), "Logger".to_string(), 46.0)));
+    non_interactable_components.insert(LOGGER_TITLE, NonInteractable::TextBlock(TextBlock::new(1161.0, 33.0, Color::new(0.05, 0.05, 0.05, 1.0), "Tab 1 Logger".to_string(), 46.0)));
 
-    non_interactable_components.insert(54, NonInteractable::ScreenDecoration(ScreenDecoration::new(335.0, 475.0, 310.0, 125.0, Color::new(0.05, 0.05, 0.05, 1.0))));
-    non_interactable_components.insert(55, NonInteractable::ScreenDecoration(ScreenDecoration::new(340.0, 480.0, 300.0, 115.0, Color::new(0.5, 0.2, 0.2, 1.0))));
-    
-    non_interactable_components.insert(72, NonInteractable::TextBlock(TextBlock::new(350.0, 510.0, Color::new(0.05, 0.05, 0.05, 1.0), "Warning".to_string(), 40.0)));
-    non_interactable_components.insert(73, NonInteractable::TextBlock(TextBlock::new(350.0, 535.0, Color::new(0.05, 0.05, 0.05, 1.0), "SFTP actions are a thread-".to_string(), 20.0)));
-    non_interactable_components.insert(74, NonInteractable::TextBlock(TextBlock::new(350.0, 555.0, Color::new(0.05, 0.05, 0.05, 1.0), "blocking action".to_string(), 20.0)));
-    non_interactable_components.insert(75, NonInteractable::TextBlock(TextBlock::new(350.0, 575.0, Color::new(0.05, 0.05, 0.05, 1.0), "The screen may go unresponsive ".to_string(), 20.0)));
+    // The tab strip above the logger, each tab gets its own session once selected
+    for tab in 0..MAX_SESSIONS {
+        let x: f32 = 686.0 + tab as f32 * 105.0;
+
+        only_interactable_components.insert(SESSION_TAB_BUTTON + tab, OnlyInteractable::Button(
+            Button::new(x, 10.0, 95.0, 34.0,
+                Color::new(0.5, 0.2, 0.2, 1.0),
+                Color::new(0.8, 0.5, 0.5, 1.0),
+                Color::new(0.3, 0.01, 0.01, 1.0),
+                Box::new(SwitchSession(tab)),
+                TextBlock::new(x + 20.0, 33.0, Color::new(1.0, 1.0, 1.0, 1.0), format!("Tab {}", tab + 1), 20.0)
+            )
+        ));
+        non_interactable_components.insert(SESSION_TAB_DCR + tab,  NonInteractable::ScreenDecoration(ScreenDecoration::new(x - 5.0, 5.0, 105.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+    }
+
+    // The vault sits at the other end of the tab strip, past the logger's title
+    only_interactable_components.insert(VAULT_BOX, OnlyInteractable::TextBox(
+        TextBox::new(1450.0, 10.0, 160.0, 34.0,
+            Color::new(0.9, 0.9, 0.9, 1.0),
+            Color::new(1.0, 1.0, 1.0, 1.0),
+            Color::new(0.7, 0.7, 0.7, 1.0),
+            "Vault password".to_string(),
+            Box::new(UnlockVault),
+            TextBlock::new(1458.0, 33.0, Color::new(0.0, 0.0, 0.0, 1.0), String::new(), 20.0),
+            true,
+            true,
+            true
+        )
+    ));
+    non_interactable_components.insert(VAULT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(1445.0, 5.0, 170.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(SAVE_TO_VAULT_BUTTON, OnlyInteractable::Button(
+        Button::new(1625.0, 10.0, 65.0, 34.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(SaveToVault),
+            TextBlock::new(1637.0, 33.0, Color::new(1.0, 1.0, 1.0, 1.0), "Save".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(SAVE_TO_VAULT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(1620.0, 5.0, 75.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
+
+    only_interactable_components.insert(LOCK_VAULT_BUTTON, OnlyInteractable::Button(
+        Button::new(1700.0, 10.0, 63.0, 34.0,
+            Color::new(0.5, 0.2, 0.2, 1.0),
+            Color::new(0.8, 0.5, 0.5, 1.0),
+            Color::new(0.3, 0.01, 0.01, 1.0),
+            Box::new(LockVault),
+            TextBlock::new(1712.0, 33.0, Color::new(1.0, 1.0, 1.0, 1.0), "Lock".to_string(), 20.0)
+        )
+    ));
+    non_interactable_components.insert(LOCK_VAULT_DCR,  NonInteractable::ScreenDecoration(ScreenDecoration::new(1695.0, 5.0, 73.0, 44.0, Color::new(0.05, 0.05, 0.05, 1.0))));
 
     hidden_components.insert(SSHCLIENT, HiddenManager::SSHClient(SSHClient::new()));
 }
@@ -4,7 +4,10 @@ use std::collections::BTreeMap;
 use crate::window_objects::window_object_center::HiddenManager;
 use crate::window_objects::window_object_center::NonInteractable;
 
+use crate::interactable_implementations::active_session;
+
 use crate::object_ids::*;
+use crate::ssh_helpers::session_worker::ForwardAction;
 
 // Buttons run a command, and so that buttons with different methods can be stored in the same Box
 // they have a struct which implements the below trait.
@@ -18,7 +21,7 @@ pub struct SSHConnect;
 impl ButtonHandler for SSHConnect {
     fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
                
-        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&SSHCLIENT) {
+        if let Some(obj) = active_session(win_man_hiddens) {
             // Hostname
             // Username
             // Password
@@ -31,3 +34,189 @@ impl ButtonHandler for SSHConnect {
         None
     }
 }
+
+// Same as SSHConnect, but authenticates with the identities held by ssh-agent
+pub struct SSHAgentConnect;
+
+impl ButtonHandler for SSHAgentConnect {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_agent_login();
+            obj.update_login_field_values(HOSTNAME_BOX, USERNAME_BOX, PASSWORD_BOX, PUBLIC_KEY_BOX, PRIVATE_KEY_BOX, PASSPHRASE_BOX);
+        }
+        None
+    }
+}
+
+// Answers to the trust-on-first-use prompt for an unknown host key
+pub struct TrustHostKey;
+pub struct RejectHostKey;
+
+impl ButtonHandler for TrustHostKey {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.set_host_key_decision(true);
+        }
+        None
+    }
+}
+
+impl ButtonHandler for RejectHostKey {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.set_host_key_decision(false);
+        }
+        None
+    }
+}
+
+// The forward textbox holds the -L spec to add, or the local port to stop
+pub struct AddLocalForward;
+pub struct AddRemoteForward;
+pub struct StopForward;
+pub struct ToggleSocksProxy;
+pub struct ReconnectSession;
+pub struct LogoutSession;
+pub struct CancelCommand;
+
+// Fill the key boxes from ~/.ssh, or from a key copied to the clipboard
+pub struct PickKey;
+pub struct PasteKey;
+
+// Saves the login fields to the vault, or locks it
+pub struct SaveToVault;
+pub struct LockVault;
+
+impl ButtonHandler for AddLocalForward {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_forward_action(ForwardAction::AddLocal);
+        }
+        None
+    }
+}
+
+impl ButtonHandler for AddRemoteForward {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_forward_action(ForwardAction::AddRemote);
+        }
+        None
+    }
+}
+
+impl ButtonHandler for StopForward {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_forward_action(ForwardAction::Stop);
+        }
+        None
+    }
+}
+
+impl ButtonHandler for ToggleSocksProxy {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_forward_action(ForwardAction::ToggleSocks);
+        }
+        None
+    }
+}
+
+impl ButtonHandler for ReconnectSession {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_reconnect();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for LogoutSession {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_disconnect();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for PickKey {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_key_pick();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for PasteKey {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_key_paste();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for SaveToVault {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_vault_save();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for LockVault {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_vault_lock();
+        }
+        None
+    }
+}
+
+impl ButtonHandler for CancelCommand {
+    fn on_click(&self, _button_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_cancel();
+        }
+        None
+    }
+}
+
+// Selects a tab by its number, opening a new session for it the first time
+pub struct SwitchSession(pub u32);
+
+impl ButtonHandler for SwitchSession {
+    fn on_click(&self, _button_id: &u32, win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<BTreeMap<u32, NonInteractable>> {
+        let session_id: u32 = SSHCLIENT + self.0;
+
+        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get(&session_id)
+            && obj.is_active() {
+            return None;
+        }
+
+        if !win_man_hiddens.contains_key(&session_id) {
+            let new_session = active_session(win_man_hiddens)?.new_tab();
+            win_man_hiddens.insert(session_id, HiddenManager::SSHClient(new_session));
+        }
+
+        let mut clone_of_parts = win_man_parts.clone();
+
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.deactivate(&clone_of_parts);
+        }
+
+        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&session_id) {
+            obj.activate(&mut clone_of_parts);
+        }
+
+        if let Some(NonInteractable::TextBlock(title)) = clone_of_parts.get_mut(&LOGGER_TITLE) {
+            title.set_text(format!("Tab {} Logger", self.0 + 1));
+        }
+
+        Some(clone_of_parts)
+    }
+}
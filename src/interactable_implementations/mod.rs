@@ -3,6 +3,18 @@ pub mod button_implementations;
 
 use std::path::Path; 
 use std::fs;
+use std::collections::BTreeMap;
+
+use crate::window_objects::window_object_center::HiddenManager;
+use crate::window_objects::sshclient_object::SSHClient;
+
+// Buttons and textboxes act on the session in the selected tab
+pub fn active_session(win_man_hiddens: &mut BTreeMap<u32, HiddenManager>) -> Option<&mut SSHClient> {
+    win_man_hiddens.values_mut().find_map(|hidden| match hidden {
+        HiddenManager::SSHClient(obj) if obj.is_active() => Some(obj),
+        _ => None,
+    })
+}
 
 // For use in the Textboxes for uploading a directory
 // WARNING: RECURSIVE
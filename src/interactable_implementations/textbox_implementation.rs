@@ -6,12 +6,11 @@ use crate::window_objects::window_object_center::NonInteractable;
 
 use crate::interactable_implementations::get_files_in_directory;
 use crate::interactable_implementations::is_directory;
+use crate::interactable_implementations::active_session;
+use crate::ssh_helpers::secret::Secret;
 
 use crate::object_ids::*;
 
-// In the event of a command (like 'cat') which displays too much data, this is a good failsafe
-const MAX_LOGGER_LINE_LENGTH: usize = 99999;
-
 // Define structs which implement this trait; doing so allows them to all be stored in the same Box
 pub trait TextboxMethod {
     fn on_enter(&self, textbox_id: &u32, win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>, text: &str) -> Option<BTreeMap<u32, NonInteractable>>;
@@ -23,6 +22,8 @@ pub struct ExecuteCommand;
 pub struct DownloadFile;
 pub struct UploadDirectory;
 pub struct UploadFile;
+pub struct AnswerPrompt;
+pub struct UnlockVault;
 
 // Not all text boxes should do something upon pressing enter directly
 impl TextboxMethod for DoNothing {
@@ -50,39 +51,22 @@ impl TextboxMethod for ExecuteCommand {
         let mut clone_of_parts = win_man_parts.clone();
 
         // Mandatory line to confirm the obj is an SSHClient
-        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&SSHCLIENT) {
+        if let Some(obj) = active_session(win_man_hiddens) {
            
             // Confirm the user is logged in and that the session has not an invalidating error
             if obj.get_login_status() && obj.is_session_still_valid() {
 
-                // Run command, where the boolean is whether it should be added to the list 
-                // of previous commands (Always true in this context)
-                let result: Result<Vec<String>, String> = obj.execute_command(text, true);
-
                 // Confirm the logger object still exists
                 if let Some(NonInteractable::Logger(log_obj)) = clone_of_parts.get_mut(&LOGGER) {
 
-                    log_obj.add_line(&format!(">>> {}", &text));
-
-                    match result {
-                        // Val is a Vector
-                        Ok(val) => {
-                            // Filter out excessive results
-                            for l in val {
-                                if l.len() > MAX_LOGGER_LINE_LENGTH {
-                                    log_obj.add_line(&l[0..MAX_LOGGER_LINE_LENGTH]);
-                               } else {
-                                    log_obj.add_line(&l)
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log_obj.add_line(&format!("Execution Error: {}", &e));
-                        }
-                    }                    
-                    // If the command was 'clear', clear the log
+                    // If the command was 'clear', clear the log rather than sending it to the server
                     if text == "clear" {
                         log_obj.clear_lines(); 
+                    } else {
+                        log_obj.add_line(&format!(">>> {}", &text));
+
+                        // The worker streams the output into the log line by line as the command runs
+                        obj.request_command(text);
                     }
                 }
             } else {
@@ -100,38 +84,14 @@ impl TextboxMethod for ExecuteCommand {
 
 impl TextboxMethod for DownloadFile {
     fn on_enter(&self, _textbox_id: &u32, win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>, text: &str) -> Option<BTreeMap<u32, NonInteractable>> { 
-        let mut clone_of_parts = win_man_parts.clone();
+        let clone_of_parts = win_man_parts.clone();
 
-        // Confirm you have the logger and SSHCLient
-        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&SSHCLIENT) {
-            if let Some(NonInteractable::Logger(log_obj)) = clone_of_parts.get_mut(&LOGGER) { 
+        if let Some(obj) = active_session(win_man_hiddens) {
 
-                // Ensure it is logged in and valid
-                if obj.get_login_status() && obj.is_session_still_valid() {
-
-                    // Require the directory, dont add command to previous ones
-                    let directory: Result<Vec<String>, String> = obj.execute_command("pwd", false);
-                
-                    match directory {
-                        Ok(contains_directory) => {
-                            // Incase the list of previous commands print anything, have to get the last
-                            // item from the list of outputs
-                            let result: Result<String, String> = obj.download_file(text, &contains_directory[contains_directory.len()-1]);
-                            
-                            match result {
-                                Ok(filepath) => {
-                                    log_obj.add_line(&format!("Downloaded file to {}", filepath)); 
-                                }
-                                Err(e) => {
-                                    log_obj.add_line(&e);
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            log_obj.add_line(&err);
-                        }
-                    }
-                }
+            // Ensure it is logged in and valid
+            if obj.get_login_status() && obj.is_session_still_valid() {
+                // Downloaded from the remote working directory
+                obj.request_download(text);
             }
         }
 
@@ -149,65 +109,20 @@ impl TextboxMethod for UploadDirectory {
         if is_directory(text) {
             
             // Once again confirm these are valid
-            if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&SSHCLIENT) {
-                if let Some(NonInteractable::Logger(log_obj)) = clone_of_parts.get_mut(&LOGGER) {
+            if let Some(obj) = active_session(win_man_hiddens) {
+                if obj.get_login_status() && obj.is_session_still_valid() {
 
-                    // Get files and directories. Loop through directories and create them in the
-                    // new location if they haven't been already
+                    // The local walk is quick, the worker then creates the directories and uploads the files
                     let dir_files_and_directories = get_files_in_directory(text).map_err(|err| err).ok()?;
-                    let mut directory_success: bool = true;
-
-                    for mut curr_dir in dir_files_and_directories.1  {
-                        curr_dir = curr_dir.replace("\\", "/");
-                        // Log in terminal as debug info
-                        println!("Making directory {}", curr_dir);
 
-                        if obj.get_login_status() && obj.is_session_still_valid() {
+                    let files: Vec<String> = dir_files_and_directories.0.iter()
+                        .map(|file| file.replace("\\", "/"))
+                        .collect();
+                    let directories: Vec<String> = dir_files_and_directories.1.iter()
+                        .map(|dir| dir.replace("\\", "/"))
+                        .collect();
 
-                            // Require the directory
-                            let result: Result<Vec<String>, String> = obj.execute_command(&format!("mkdir {}", curr_dir), false);
-
-                            match result {
-                                Ok(_) => {/* Success */}
-                                Err(_) => {
-                                    directory_success = false;    
-                                }
-                            }
-                        }
-                    }
-
-                    for mut curr_file in dir_files_and_directories.0 {
-                        curr_file = curr_file.replace("\\", "/");
-                        // Log in terminal as debug info
-                        println!("Attempting to upload {}", curr_file);
-
-                        if obj.get_login_status() && obj.is_session_still_valid() && directory_success {
-                            
-                            // Aquire the directory
-                            let directory: Result<Vec<String>, String> = obj.execute_command("pwd", false);
-
-                            match directory {
-                                Ok(contains_directory) => {
-
-                                    // Incase the list of previous commands print anything, have to get the last
-                                    // item from the list of outputs
-                                    let result: Result<String, String> = obj.upload_file(&curr_file, &contains_directory[contains_directory.len()-1]);
-                                
-                                    match result {
-                                        Ok(filepath) => {
-                                            log_obj.add_line(&format!("Uploaded file to {}", filepath)); 
-                                        }
-                                        Err(e) => {
-                                            log_obj.add_line(&e);
-                                       }
-                                    }
-                                }
-                                Err(err) => {
-                                    log_obj.add_line(&err);
-                                }
-                            }
-                        }
-                    }
+                    obj.request_directory_upload(directories, files);
                 }
             }
         } else {
@@ -222,39 +137,42 @@ impl TextboxMethod for UploadDirectory {
 
 impl TextboxMethod for UploadFile {
     fn on_enter(&self, _textbox_id: &u32, win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>, text: &str) -> Option<BTreeMap<u32, NonInteractable>> { 
-        let mut clone_of_parts = win_man_parts.clone();
+        let clone_of_parts = win_man_parts.clone();
         
-        if let Some(HiddenManager::SSHClient(obj)) = win_man_hiddens.get_mut(&SSHCLIENT) {
-            if let Some(NonInteractable::Logger(log_obj)) = clone_of_parts.get_mut(&LOGGER) { 
-                if obj.get_login_status() && obj.is_session_still_valid() {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            if obj.get_login_status() && obj.is_session_still_valid() {
+                // Uploaded into the remote working directory
+                obj.request_upload(text);
+            }
+        }
 
-                    // Require the directory
-                    let directory: Result<Vec<String>, String> = obj.execute_command("pwd", false);
-                
-                    match directory {
-                        Ok(contains_directory) => {
-
-                            // Incase the list of previous commands print anything, have to get the last
-                            // item from the list of outputs
-                            let result: Result<String, String> = obj.upload_file(text, &contains_directory[contains_directory.len()-1]);
-                            
-                            match result {
-                                Ok(filepath) => {
-                                    log_obj.add_line(&format!("Uploaded file to {}", filepath)); 
-                                }
-                                Err(e) => {
-                                    log_obj.add_line(&e);
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            log_obj.add_line(&err);
-                        }
-                    }
-                }
+        Some(clone_of_parts)
+    }
+}
+
+
+impl TextboxMethod for AnswerPrompt {
+    fn on_enter(&self, _textbox_id: &u32, win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>, text: &str) -> Option<BTreeMap<u32, NonInteractable>> { 
+        let mut clone_of_parts = win_man_parts.clone();
+
+        if let Some(obj) = active_session(win_man_hiddens) {
+            // The answer itself is never logged as it is usually a password or one-time code
+            if !obj.answer_prompt(text)
+                && let Some(NonInteractable::Logger(log_obj)) = clone_of_parts.get_mut(&LOGGER) {
+                log_obj.add_line("[SSH INFO] The server isn't waiting on an answer");
             }
         }
 
         Some(clone_of_parts)
     }
 }
+
+// The vault's master password, the box clears itself on enter
+impl TextboxMethod for UnlockVault {
+    fn on_enter(&self, _textbox_id: &u32, _win_man_parts: BTreeMap<u32, NonInteractable>, win_man_hiddens: &mut BTreeMap<u32, HiddenManager>, text: &str) -> Option<BTreeMap<u32, NonInteractable>> {
+        if let Some(obj) = active_session(win_man_hiddens) {
+            obj.request_vault_unlock(Secret::new(text.to_string()));
+        }
+        None
+    }
+}
@@ -8,6 +8,7 @@ mod managers;
 mod assets; 
 mod object_ids;
 mod init_graphics_objects;
+mod ssh_helpers;
 
 use crate::managers::user_interaction::UserInteractionManager;
 use crate::managers::user_interaction::UserInteractionManagerMethods;
@@ -27,14 +28,100 @@ pub struct InputArgs {
     #[arg(long)]
     username: Option<String>,
 
+    // Used when the hostname doesn't include one
+    #[arg(long)]
+    port: Option<u16>,
+
+    // Seconds to wait for the connection and handshake, otherwise the config's ConnectTimeout
+    #[arg(long)]
+    connect_timeout: Option<u64>,
+
+    // Seconds a command can run before it's interrupted, otherwise the config's TrsshCommandTimeout. 0 or unset lets it run forever
+    #[arg(long)]
+    command_timeout: Option<u64>,
+
+    // Seconds an SFTP read or write can stall before the transfer fails, otherwise the config's TrsshTransferTimeout
+    #[arg(long)]
+    transfer_timeout: Option<u64>,
+
     #[arg(long)]
     public: Option<String>,
 
     #[arg(long)]
     private: Option<String>,
 
+    // Reads the key's passphrase from stdin at startup, so it isn't left in the process list. The
+    // TRSSH_PASSPHRASE environment variable works too
+    #[arg(long)]
+    passphrase_stdin: bool,
+
+    // Program asked for a passphrase when an encrypted key has none, otherwise SSH_ASKPASS
+    #[arg(long)]
+    askpass: Option<String>,
+
+    // An OpenSSH certificate to log in with alongside the private key, otherwise <key>-cert.pub is used if it's there
+    #[arg(long)]
+    certificate: Option<String>,
+
+    // Comma separated jump hosts, like ssh -J
+    #[arg(long)]
+    jump: Option<String>,
+
+    // Starts a SOCKS5 proxy on [bind_address:]port once logged in, like ssh -D
+    #[arg(long)]
+    socks: Option<String>,
+
+    // Seconds between keepalives, 0 turns them off
+    #[arg(long)]
+    keepalive: Option<u32>,
+
+    // Logs back in by itself if the connection drops
+    #[arg(long)]
+    reconnect: bool,
+
+    // Used instead of ~/.ssh/config
+    #[arg(long)]
+    config: Option<String>,
+
+    // Makes the Login button authenticate through ssh-agent
+    #[arg(long)]
+    agent: bool,
+
+    // The encrypted file saved logins are kept in, otherwise ~/.ssh/trssh.vault
+    #[arg(long)]
+    vault: Option<String>,
+
+    // Seconds the vault can go unused before it locks itself, 0 keeps it unlocked
+    #[arg(long)]
+    vault_timeout: Option<u64>,
+
+    // NAME=value set in every shell, can be given more than once. Adds to the config's SetEnv
+    #[arg(long)]
+    env: Vec<String>,
+
+    // Run in every new shell after logging in, like module load, can be given more than once
+    #[arg(long)]
+    startup_command: Vec<String>,
+
+    // Replaces the default line that stops the shell printing prompts, for shells other than bash and zsh
+    #[arg(long)]
+    shell_setup: Option<String>,
+
+    // Key exchange algorithms to offer, like ssh's KexAlgorithms, so +, - and ^ work on the defaults
+    #[arg(long)]
+    kex_algorithms: Option<String>,
+
+    // Host key algorithms to accept, like ssh's HostKeyAlgorithms
+    #[arg(long)]
+    host_key_algorithms: Option<String>,
+
+    // Ciphers to offer, like ssh's Ciphers
+    #[arg(long)]
+    ciphers: Option<String>,
+
+    // MACs to offer, like ssh's MACs
     #[arg(long)]
-    passphrase: Option<String>,
+    macs: Option<String>,
 }
 
 const SCREEN_WIDTH: i32 = 1772;
@@ -14,6 +14,17 @@ use crate::window_objects::window_object_center::HiddenObjectMethods;
 use crate::init_graphics_objects::init_graphics_objects_main;
 
 use crate::InputArgs;
+use crate::object_ids::SSHCLIENT;
+use crate::object_ids::JUMP_BOX;
+
+use crate::ssh_helpers::secret::Secret;
+use crate::ssh_helpers::secret::read_secret_from_stdin;
+use crate::ssh_helpers::secret::PASSPHRASE_VARIABLE;
+use crate::ssh_helpers::secret::ASKPASS_VARIABLE;
+use crate::ssh_helpers::algorithms::AlgorithmPreferences;
+
+use std::time::Duration;
+use std::path::PathBuf;
 
 use clap::Parser;
 
@@ -101,6 +112,80 @@ impl WindowManagerMethods for WindowManager {
             }
         }
 
+        if let Some(jump) = input_args.jump
+            && let Some(OnlyInteractable::TextBox(obj)) = self.only_interactable_components.get_mut(&JUMP_BOX) {
+            obj.force_set_text(jump);
+        }
+
+        if let Some(HiddenManager::SSHClient(obj)) = self.hidden_components.get_mut(&SSHCLIENT) {
+            if let Some(port) = input_args.port {
+                obj.set_default_port(port);
+            }
+
+            if let Some(seconds) = input_args.connect_timeout {
+                obj.set_connect_timeout(Duration::from_secs(seconds));
+            }
+
+            if let Some(seconds) = input_args.command_timeout {
+                obj.set_command_timeout(Duration::from_secs(seconds));
+            }
+
+            if let Some(seconds) = input_args.transfer_timeout {
+                obj.set_transfer_timeout(Duration::from_secs(seconds));
+            }
+
+            if let Some(config) = input_args.config {
+                obj.set_config_path(PathBuf::from(config));
+            }
+
+            if let Some(vault) = input_args.vault {
+                obj.set_vault_path(PathBuf::from(vault));
+            }
+
+            if let Some(seconds) = input_args.vault_timeout {
+                obj.set_vault_timeout(Duration::from_secs(seconds));
+            }
+
+            if let Some(certificate) = input_args.certificate {
+                obj.set_certificate_path(PathBuf::from(certificate));
+            }
+
+            // Taken from stdin or the environment rather than an argument, which anyone can see in ps
+            let passphrase: Option<Secret> = if input_args.passphrase_stdin {
+                read_secret_from_stdin("Key passphrase: ")
+                    .inspect_err(|message| eprintln!("{}", message))
+                    .ok()
+            } else {
+                std::env::var(PASSPHRASE_VARIABLE).ok().map(Secret::new)
+            };
+
+            if let Some(passphrase) = passphrase {
+                obj.set_passphrase(passphrase);
+            }
+
+            if let Some(program) = input_args.askpass.or(std::env::var(ASKPASS_VARIABLE).ok()).filter(|program| !program.is_empty()) {
+                obj.set_askpass(PathBuf::from(program));
+            }
+
+            obj.set_prefer_agent(input_args.agent);
+            obj.set_auto_reconnect(input_args.reconnect);
+            obj.set_shell_profile(input_args.env, input_args.startup_command, input_args.shell_setup);
+            obj.set_algorithms(AlgorithmPreferences {
+                kex: input_args.kex_algorithms,
+                host_key: input_args.host_key_algorithms,
+                ciphers: input_args.ciphers,
+                macs: input_args.macs,
+            });
+
+            if let Some(seconds) = input_args.keepalive {
+                obj.set_keepalive_interval(seconds);
+            }
+
+            if let Some(socks) = input_args.socks {
+                obj.set_socks_spec(socks);
+            }
+        }
+
         if let Some(public) = input_args.public {
             if let Some(OnlyInteractable::TextBox(obj)) = self.only_interactable_components.get_mut(&4) {
                 obj.force_set_text(public);
@@ -113,11 +198,6 @@ impl WindowManagerMethods for WindowManager {
             }
         }
 
-        if let Some(passphrase) = input_args.passphrase {
-            if let Some(OnlyInteractable::TextBox(obj)) = self.only_interactable_components.get_mut(&6) {
-                obj.force_set_text(passphrase);
-            }
-        }
     }
 
     fn update(&mut self) {
@@ -35,3 +35,66 @@ pub const SSHCLIENT: u32 = 100;
 
 pub const LOGIN_BUTTON: u32 = 7;
 pub const LOGIN_DCR: u32 = 107;
+
+pub const TRUST_HOST_BUTTON: u32 = 12;
+pub const TRUST_HOST_DCR: u32 = 112;
+
+pub const REJECT_HOST_BUTTON: u32 = 13;
+pub const REJECT_HOST_DCR: u32 = 113;
+
+pub const AGENT_LOGIN_BUTTON: u32 = 14;
+pub const AGENT_LOGIN_DCR: u32 = 114;
+
+pub const PROMPT_BOX: u32 = 15;
+pub const PROMPT_DCR: u32 = 115;
+
+pub const JUMP_BOX: u32 = 16;
+pub const JUMP_DCR: u32 = 116;
+
+pub const FORWARD_BOX: u32 = 17;
+pub const FORWARD_DCR: u32 = 117;
+
+pub const ADD_FORWARD_BUTTON: u32 = 18;
+pub const ADD_FORWARD_DCR: u32 = 118;
+
+pub const STOP_FORWARD_BUTTON: u32 = 19;
+pub const STOP_FORWARD_DCR: u32 = 119;
+
+pub const ADD_REMOTE_FORWARD_BUTTON: u32 = 20;
+pub const ADD_REMOTE_FORWARD_DCR: u32 = 120;
+
+pub const SOCKS_BUTTON: u32 = 21;
+pub const SOCKS_DCR: u32 = 121;
+
+pub const RECONNECT_BUTTON: u32 = 22;
+pub const RECONNECT_DCR: u32 = 122;
+
+pub const LOGOUT_BUTTON: u32 = 27;
+pub const LOGOUT_DCR: u32 = 127;
+
+pub const CANCEL_BUTTON: u32 = 28;
+pub const CANCEL_DCR: u32 = 128;
+
+pub const PICK_KEY_BUTTON: u32 = 29;
+pub const PICK_KEY_DCR: u32 = 129;
+
+pub const PASTE_KEY_BUTTON: u32 = 30;
+pub const PASTE_KEY_DCR: u32 = 130;
+
+pub const VAULT_BOX: u32 = 31;
+pub const VAULT_DCR: u32 = 131;
+
+pub const SAVE_TO_VAULT_BUTTON: u32 = 32;
+pub const SAVE_TO_VAULT_DCR: u32 = 132;
+
+pub const LOCK_VAULT_BUTTON: u32 = 33;
+pub const LOCK_VAULT_DCR: u32 = 133;
+
+// SESSION_TAB_BUTTON + n selects tab n, whose session is kept under SSHCLIENT + n
+pub const SESSION_TAB_BUTTON: u32 = 23;
+pub const SESSION_TAB_DCR: u32 = 123;
+pub const MAX_SESSIONS: u32 = 4;
+
+pub const LOGGER_TITLE: u32 = 71;
+
+pub const TUNNEL_LIST: u32 = 57;
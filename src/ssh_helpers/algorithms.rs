@@ -0,0 +1,207 @@
+use ssh2::MethodType;
+use ssh2::Session;
+
+use crate::ssh_helpers::ssh_config::wildcard_match;
+
+// The algorithm lists offered to the server, written like OpenSSH's KexAlgorithms and friends. A
+// plain list replaces libssh2's own, one starting with + adds to it, - takes away from it and ^
+// moves to the front of it. Names may contain wildcards, and None leaves libssh2's defaults alone
+#[derive(Clone, Debug, Default)]
+pub struct AlgorithmPreferences {
+    pub kex: Option<String>,
+    pub host_key: Option<String>,
+    pub ciphers: Option<String>,
+    pub macs: Option<String>,
+}
+
+impl AlgorithmPreferences {
+    // Each list set here wins over the same list in the other
+    pub fn or(&self, other: &AlgorithmPreferences) -> AlgorithmPreferences {
+        AlgorithmPreferences {
+            kex: self.kex.clone().or(other.kex.clone()),
+            host_key: self.host_key.clone().or(other.host_key.clone()),
+            ciphers: self.ciphers.clone().or(other.ciphers.clone()),
+            macs: self.macs.clone().or(other.macs.clone()),
+        }
+    }
+
+    // Named as they are in ssh_config. Ciphers and MACs are set for both directions
+    fn lists(&self) -> [(&'static str, &Option<String>, &'static [MethodType]); 4] {
+        [
+            ("KexAlgorithms", &self.kex, &[MethodType::Kex]),
+            ("HostKeyAlgorithms", &self.host_key, &[MethodType::HostKey]),
+            ("Ciphers", &self.ciphers, &[MethodType::CryptCs, MethodType::CryptSc]),
+            ("MACs", &self.macs, &[MethodType::MacCs, MethodType::MacSc]),
+        ]
+    }
+
+    // Must be called before the handshake. Returns warnings about names libssh2 doesn't know
+    pub fn apply(&self, session: &Session) -> Result<Vec<String>, String> {
+        let mut warnings: Vec<String> = Vec::new();
+
+        for (keyword, list, method_types) in self.lists() {
+            let Some(list) = list else {
+                continue;
+            };
+
+            let supported: Vec<&str> = supported_algorithms(session, method_types[0], keyword)?;
+            let (chosen, unknown) = resolve_list(list, &supported);
+
+            if !unknown.is_empty() {
+                warnings.push(format!("[SSH ALGORITHMS] Ignoring {} {}, this build of libssh2 doesn't support them", keyword, unknown.join(", ")));
+            }
+
+            if chosen.is_empty() {
+                return Err(format!("[SSH ALGORITHMS] {} \"{}\" leaves nothing to offer the server", keyword, list));
+            }
+
+            for method_type in method_types {
+                session.method_pref(*method_type, &chosen.join(","))
+                    .map_err(|e| format!("[SSH ALGORITHMS] Could not set {} ({})", keyword, e.message()))?;
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    // What each list offered, for when the server wouldn't agree to any of it
+    pub fn describe_offered(&self, session: &Session) -> Vec<String> {
+        self.lists().iter()
+            .map(|(keyword, list, method_types)| {
+                let supported: Vec<&str> = supported_algorithms(session, method_types[0], keyword).unwrap_or_default();
+                let offered: String = match list {
+                    Some(list) => resolve_list(list, &supported).0.join(","),
+                    None => format!("libssh2's defaults, {}", supported.join(",")),
+                };
+
+                format!("[SSH HELP] ...{} offered: {}", keyword, offered)
+            })
+            .collect()
+    }
+}
+
+// The methods agreed on in the handshake, logged once it's done
+pub fn describe_negotiated(session: &Session) -> Vec<String> {
+    let method = |method_type: MethodType| session.methods(method_type).unwrap_or("unknown").to_string();
+
+    // Only worth showing both directions when they differ
+    let both_ways = |outgoing: MethodType, incoming: MethodType| {
+        let (outgoing, incoming) = (method(outgoing), method(incoming));
+
+        if outgoing == incoming {
+            outgoing
+        } else {
+            format!("{} out, {} in", outgoing, incoming)
+        }
+    };
+
+    vec![
+        format!("[SSH ALGORITHMS] Key exchange {}, host key {}", method(MethodType::Kex), method(MethodType::HostKey)),
+        format!("[SSH ALGORITHMS] ...Cipher {}, MAC {}",
+            both_ways(MethodType::CryptCs, MethodType::CryptSc),
+            both_ways(MethodType::MacCs, MethodType::MacSc)),
+    ]
+}
+
+fn supported_algorithms(session: &Session, method_type: MethodType, keyword: &str) -> Result<Vec<&'static str>, String> {
+    session.supported_algs(method_type)
+        .map_err(|e| format!("[SSH ALGORITHMS] Could not list the supported {} ({})", keyword, e.message()))
+}
+
+// libssh2 offers everything it supports by default, in the order supported_algs gives them. Returns
+// the list to offer and any names that matched nothing
+fn resolve_list<'a>(list: &str, supported: &[&'a str]) -> (Vec<&'a str>, Vec<String>) {
+    let (prefix, names) = match list.chars().next() {
+        Some(c @ ('+' | '-' | '^')) => (Some(c), &list[1..]),
+        _ => (None, list),
+    };
+
+    let mut named: Vec<&'a str> = Vec::new();
+    let mut unknown: Vec<String> = Vec::new();
+
+    for name in names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+        let matches: Vec<&'a str> = supported.iter()
+            .copied()
+            .filter(|algorithm| wildcard_match(name, algorithm))
+            .collect();
+
+        if matches.is_empty() {
+            unknown.push(name.to_string());
+        }
+
+        for algorithm in matches {
+            if !named.contains(&algorithm) {
+                named.push(algorithm);
+            }
+        }
+    }
+
+    let chosen: Vec<&'a str> = match prefix {
+        // Everything supported is already offered, so adding only checks the names exist
+        Some('+') => supported.to_vec(),
+        Some('-') => supported.iter().copied().filter(|algorithm| !named.contains(algorithm)).collect(),
+        Some('^') => {
+            let mut moved: Vec<&'a str> = named.clone();
+            moved.extend(supported.iter().filter(|algorithm| !named.contains(algorithm)));
+            moved
+        }
+        _ => named,
+    };
+
+    (chosen, unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: [&str; 4] = ["curve25519-sha256", "ecdh-sha2-nistp256", "diffie-hellman-group14-sha256", "diffie-hellman-group14-sha1"];
+
+    #[test]
+    fn plain_lists_replace_the_defaults() {
+        let (chosen, unknown) = resolve_list("diffie-hellman-group14-sha256, curve25519-sha256", &SUPPORTED);
+
+        assert_eq!(chosen, vec!["diffie-hellman-group14-sha256", "curve25519-sha256"]);
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn prefixes_change_the_defaults() {
+        assert_eq!(resolve_list("+curve25519-sha256", &SUPPORTED).0, SUPPORTED.to_vec());
+        assert_eq!(resolve_list("-diffie-hellman-group14-sha1", &SUPPORTED).0, SUPPORTED[..3].to_vec());
+        assert_eq!(resolve_list("^diffie-hellman-group14-sha1", &SUPPORTED).0,
+            vec!["diffie-hellman-group14-sha1", "curve25519-sha256", "ecdh-sha2-nistp256", "diffie-hellman-group14-sha256"]);
+    }
+
+    #[test]
+    fn expands_wildcards() {
+        assert_eq!(resolve_list("-diffie-hellman-*", &SUPPORTED).0, vec!["curve25519-sha256", "ecdh-sha2-nistp256"]);
+        assert_eq!(resolve_list("*sha256", &SUPPORTED).0, vec!["curve25519-sha256", "diffie-hellman-group14-sha256"]);
+
+        // A name matched twice is only offered once
+        assert_eq!(resolve_list("curve*,*25519*", &SUPPORTED).0, vec!["curve25519-sha256"]);
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let (chosen, unknown) = resolve_list("sntrup761x25519-sha512,curve25519-sha256,made-up-*", &SUPPORTED);
+
+        assert_eq!(chosen, vec!["curve25519-sha256"]);
+        assert_eq!(unknown, vec!["sntrup761x25519-sha512", "made-up-*"]);
+
+        let (chosen, unknown) = resolve_list("-*", &SUPPORTED);
+        assert!(chosen.is_empty());
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn later_preferences_fill_gaps() {
+        let command_line = AlgorithmPreferences { kex: Some("curve25519-sha256".to_string()), ..Default::default() };
+        let config = AlgorithmPreferences { kex: Some("-*".to_string()), macs: Some("hmac-sha2-256".to_string()), ..Default::default() };
+
+        let combined: AlgorithmPreferences = command_line.or(&config);
+        assert_eq!(combined.kex.as_deref(), Some("curve25519-sha256"));
+        assert_eq!(combined.macs.as_deref(), Some("hmac-sha2-256"));
+        assert_eq!(combined.ciphers, None);
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+// Certificates valid forever have this as their expiry
+const NEVER_EXPIRES: u64 = u64::MAX;
+
+// A certificate's type field, host certificates can't log anyone in
+const USER_CERTIFICATE: u32 = 1;
+
+// How soon before it expires a certificate is warned about
+pub const CERTIFICATE_WARNING_SECONDS: u64 = 15 * 60;
+
+// The parts of an OpenSSH user certificate worth telling the user about
+#[derive(Clone)]
+pub struct UserCertificate {
+    pub path: PathBuf,
+    pub key_id: String,
+    // Empty means it's valid for any username
+    pub principals: Vec<String>,
+    // Seconds since the epoch
+    pub valid_after: u64,
+    pub valid_before: u64,
+}
+
+// ssh-keygen -s writes the certificate for id_ed25519 to id_ed25519-cert.pub
+pub fn certificate_path_for(private_key: &Path) -> PathBuf {
+    PathBuf::from(format!("{}-cert.pub", private_key.display()))
+}
+
+impl UserCertificate {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents: String = fs::read_to_string(path)
+            .map_err(|_| format!("[SSH CERT] Could not read the certificate {}", path.display()))?;
+
+        let not_certificate = || format!("[SSH CERT] {} is not an OpenSSH certificate", path.display());
+
+        let mut fields = contents.split_whitespace();
+        let key_type: &str = fields.next().unwrap_or_default();
+        let blob: Vec<u8> = fields.next()
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .ok_or_else(not_certificate)?;
+
+        // The public key's own fields come between the nonce and the serial, and differ by key type
+        let key_fields: usize = match key_type {
+            "ssh-ed25519-cert-v01@openssh.com" => 1,
+            "ssh-rsa-cert-v01@openssh.com" => 2,
+            "ecdsa-sha2-nistp256-cert-v01@openssh.com" => 2,
+            "ecdsa-sha2-nistp384-cert-v01@openssh.com" => 2,
+            "ecdsa-sha2-nistp521-cert-v01@openssh.com" => 2,
+            "sk-ssh-ed25519-cert-v01@openssh.com" => 2,
+            "sk-ecdsa-sha2-nistp256-cert-v01@openssh.com" => 3,
+            "ssh-dss-cert-v01@openssh.com" => 4,
+            _ => return Err(not_certificate()),
+        };
+
+        let mut reader = BlobReader { blob: &blob, position: 0 };
+
+        // The type is repeated inside the blob, then comes a random nonce
+        if reader.string()? != key_type.as_bytes() {
+            return Err(not_certificate());
+        }
+        reader.string()?;
+
+        for _ in 0..key_fields {
+            reader.string()?;
+        }
+
+        let _serial: u64 = reader.u64()?;
+
+        if reader.u32()? != USER_CERTIFICATE {
+            return Err(format!("[SSH CERT] {} is a host certificate, not a user certificate", path.display()));
+        }
+
+        let key_id: String = String::from_utf8_lossy(reader.string()?).to_string();
+
+        // The principals are a list of strings packed inside one string
+        let mut principal_reader = BlobReader { blob: reader.string()?, position: 0 };
+        let mut principals: Vec<String> = Vec::new();
+
+        while principal_reader.position < principal_reader.blob.len() {
+            principals.push(String::from_utf8_lossy(principal_reader.string()?).to_string());
+        }
+
+        Ok(UserCertificate {
+            path: path.to_path_buf(),
+            key_id,
+            principals,
+            valid_after: reader.u64()?,
+            valid_before: reader.u64()?,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.valid_before != NEVER_EXPIRES && now_seconds() >= self.valid_before
+    }
+
+    // None for certificates that never expire
+    pub fn seconds_left(&self) -> Option<u64> {
+        (self.valid_before != NEVER_EXPIRES).then(|| self.valid_before.saturating_sub(now_seconds()))
+    }
+
+    pub fn allows(&self, username: &str) -> bool {
+        self.principals.is_empty() || self.principals.iter().any(|principal| principal == username)
+    }
+
+    // The lines logged when the certificate is used
+    pub fn describe(&self) -> Vec<String> {
+        let principals: String = if self.principals.is_empty() {
+            "any user".to_string()
+        } else {
+            self.principals.join(", ")
+        };
+
+        let expiry: String = match self.seconds_left() {
+            None => "never expires".to_string(),
+            Some(0) => format!("expired {}", format_utc(self.valid_before)),
+            Some(seconds) => format!("expires {} (in {})", format_utc(self.valid_before), format_duration(seconds)),
+        };
+
+        // ssh-keygen leaves valid_after at 0 when no start time was given
+        let valid_from: String = if self.valid_after == 0 {
+            "always".to_string()
+        } else {
+            format_utc(self.valid_after)
+        };
+
+        vec![
+            format!("[SSH CERT] Using certificate {} (ID \"{}\")", self.path.display(), self.key_id),
+            format!("[SSH CERT] ...Principals: {}", principals),
+            format!("[SSH CERT] ...Valid from {}, {}", valid_from, expiry),
+        ]
+    }
+}
+
+// Reads the length-prefixed strings and big-endian integers SSH packs its keys in
+struct BlobReader<'a> {
+    blob: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BlobReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end: usize = self.position.checked_add(length)
+            .filter(|end| *end <= self.blob.len())
+            .ok_or("[SSH CERT] The certificate is cut short".to_string())?;
+
+        let bytes: &'a [u8] = &self.blob[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes: &[u8] = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let high: u64 = self.u32()? as u64;
+        let low: u64 = self.u32()? as u64;
+        Ok((high << 32) | low)
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], String> {
+        let length: usize = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+// Like 2024-03-01 14:05 UTC, worked out by hand to avoid a date crate
+fn format_utc(seconds: u64) -> String {
+    if seconds == NEVER_EXPIRES {
+        return "forever".to_string();
+    }
+
+    let days: i64 = (seconds / 86400) as i64;
+    let minutes_today: u64 = seconds % 86400 / 60;
+
+    // Howard Hinnant's days-to-civil, counting in 400 year eras from March 0000
+    let shifted: i64 = days + 719468;
+    let era: i64 = shifted.div_euclid(146097);
+    let day_of_era: i64 = shifted - era * 146097;
+    let year_of_era: i64 = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes_today / 60, minutes_today % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made with ssh-keygen -s: key ID alice-id for alice and root, valid for 2024-01-01 UTC
+    const USER_CERTIFICATE_TEXT: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIGSIvpGMZQESt/l31no2Kn+0XZOAVsovN4+jwl1L1Z2+AAAAIFtwKnuXHi87Emr5HJMLTomLt5Ac18+5oso6TTT/Frs5AAAAAAAAAAcAAAABAAAACGFsaWNlLWlkAAAAEQAAAAVhbGljZQAAAARyb290AAAAAGWSAIAAAAAAZZNSAAAAAAAAAACCAAAAFXBlcm1pdC1YMTEtZm9yd2FyZGluZwAAAAAAAAAXcGVybWl0LWFnZW50LWZvcndhcmRpbmcAAAAAAAAAFnBlcm1pdC1wb3J0LWZvcndhcmRpbmcAAAAAAAAACnBlcm1pdC1wdHkAAAAAAAAADnBlcm1pdC11c2VyLXJjAAAAAAAAAAAAAAAzAAAAC3NzaC1lZDI1NTE5AAAAIIrMQ+4/YsrXA150H8ta73CFyxT+0S+hUAFhWFqi+qguAAAAUwAAAAtzc2gtZWQyNTUxOQAAAEDGNWSJVac8/B7bqfulFJDxBXdKkTWyw4JHBuaH//YzwEtL/ZS0Qrvjlf7honKO/0dQLNgHNc6QspyW/POckogM t_user.pub";
+    // Key ID any-id, no principals, valid forever
+    const ANY_USER_CERTIFICATE_TEXT: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAINyJndQEZ8tIVaBfIAAqj/Lt4BJOfGTiX/60Ql8JOXxwAAAAIEkM9aHJsaiLN6F7/YcLG4mSiW7AlKH4/d4I7PGK8GG+AAAAAAAAAAAAAAABAAAABmFueS1pZAAAAAAAAAAAAAAAAP//////////AAAAAAAAAIIAAAAVcGVybWl0LVgxMS1mb3J3YXJkaW5nAAAAAAAAABdwZXJtaXQtYWdlbnQtZm9yd2FyZGluZwAAAAAAAAAWcGVybWl0LXBvcnQtZm9yd2FyZGluZwAAAAAAAAAKcGVybWl0LXB0eQAAAAAAAAAOcGVybWl0LXVzZXItcmMAAAAAAAAAAAAAADMAAAALc3NoLWVkMjU1MTkAAAAgisxD7j9iytcDXnQfy1rvcIXLFP7RL6FQAWFYWqL6qC4AAABTAAAAC3NzaC1lZDI1NTE5AAAAQGAb310dri5GQgRXviLWAww9u+J+z/AytwtKrpwG7w9P4xNMGz28eYYi/xvrmP6Ba2BYee3CFl4kM/SRihFnzQ8= t_any.pub";
+    // ssh-keygen -h, for the host example.com
+    const HOST_CERTIFICATE_TEXT: &str = "ssh-ed25519-cert-v01@openssh.com AAAAIHNzaC1lZDI1NTE5LWNlcnQtdjAxQG9wZW5zc2guY29tAAAAIEPs/N5D3y46OsMeMY3mkmEnjvULOVXv4qlgFkntibbBAAAAIFtwKnuXHi87Emr5HJMLTomLt5Ac18+5oso6TTT/Frs5AAAAAAAAAAAAAAACAAAAB2hvc3QtaWQAAAAPAAAAC2V4YW1wbGUuY29tAAAAAAAAAAD//////////wAAAAAAAAAAAAAAAAAAADMAAAALc3NoLWVkMjU1MTkAAAAgisxD7j9iytcDXnQfy1rvcIXLFP7RL6FQAWFYWqL6qC4AAABTAAAAC3NzaC1lZDI1NTE5AAAAQMzV8H9+5VqFRIM81yIAkYLsbkeHw0Oik3T5v8W4b7TolX9smAA1F8Df4skpuNoSgrB3+UKw5lMxIKoU1nyXGwk= t_host.pub";
+
+    fn load_text(name: &str, contents: &str) -> Result<UserCertificate, String> {
+        let path: PathBuf = std::env::temp_dir().join(format!("trssh-cert-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+
+        let certificate: Result<UserCertificate, String> = UserCertificate::load(&path);
+        fs::remove_file(&path).unwrap();
+        certificate
+    }
+
+    #[test]
+    fn reads_user_certificates() {
+        let certificate: UserCertificate = load_text("user", USER_CERTIFICATE_TEXT).unwrap();
+
+        assert_eq!(certificate.key_id, "alice-id");
+        assert_eq!(certificate.principals, vec!["alice", "root"]);
+        assert_eq!(certificate.valid_after, 1704067200);
+        assert_eq!(certificate.valid_before, 1704153600);
+        assert!(certificate.is_expired());
+        assert_eq!(certificate.seconds_left(), Some(0));
+        assert!(certificate.allows("alice"));
+        assert!(!certificate.allows("bob"));
+    }
+
+    #[test]
+    fn certificates_without_principals_allow_anyone() {
+        let certificate: UserCertificate = load_text("any", ANY_USER_CERTIFICATE_TEXT).unwrap();
+
+        assert_eq!(certificate.key_id, "any-id");
+        assert!(certificate.principals.is_empty());
+        assert!(certificate.allows("bob"));
+        assert!(!certificate.is_expired());
+        assert_eq!(certificate.seconds_left(), None);
+    }
+
+    #[test]
+    fn rejects_host_certificates() {
+        let error: String = load_text("host", HOST_CERTIFICATE_TEXT).err().unwrap();
+        assert!(error.contains("host certificate"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_things_that_are_not_certificates() {
+        let (key_type, encoded) = USER_CERTIFICATE_TEXT.split_once(' ').unwrap();
+        let truncated: String = STANDARD.encode(&STANDARD.decode(encoded.split(' ').next().unwrap()).unwrap()[..120]);
+
+        for contents in [
+            String::new(),
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIIrMQ+4/YsrXA150H8ta73CFyxT+0S+hUAFhWFqi+qgu".to_string(),
+            format!("{} not-base64!", key_type),
+            format!("{} {}", key_type, truncated),
+            format!("ssh-rsa-cert-v01@openssh.com {}", encoded),
+        ] {
+            assert!(load_text("garbage", &contents).is_err(), "{} should be rejected", contents);
+        }
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_utc(1704067200), "2024-01-01 00:00 UTC");
+        assert_eq!(format_utc(951825600), "2000-02-29 12:00 UTC");
+        assert_eq!(format_utc(NEVER_EXPIRES), "forever");
+
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(90), "1m");
+        assert_eq!(format_duration(3 * 3600 + 120), "3h 2m");
+        assert_eq!(format_duration(2 * 86400 + 3600), "2d 1h");
+    }
+}
@@ -0,0 +1,270 @@
+use ssh2::Channel;
+use ssh2::Session;
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+// How long the pump sleeps when neither side had anything to send
+const IDLE_SLEEP: Duration = Duration::from_millis(2);
+const PUMP_BUFFER_SIZE: usize = 32 * 1024;
+
+// Writes everything, retrying while the other end isn't ready. Returns false if it gave up
+fn write_all_nonblocking<W: Write>(writer: &mut W, mut data: &[u8], stop: &AtomicBool) -> bool {
+    while !data.is_empty() {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match writer.write(data) {
+            Ok(0) => return false,
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(IDLE_SLEEP),
+            Err(_) => return false,
+        }
+    }
+
+    true
+}
+
+// Copies bytes both ways between a local socket and an SSH channel until either closes or stop
+// is set. The channel's session must already be in non-blocking mode, and nothing else may be
+// using that session in blocking mode meanwhile
+pub fn pump_channel(mut channel: Channel, mut socket: TcpStream, stop: Arc<AtomicBool>) {
+    if socket.set_nonblocking(true).is_err() {
+        return;
+    }
+
+    let mut buffer = [0u8; PUMP_BUFFER_SIZE];
+
+    while !stop.load(Ordering::Relaxed) {
+        let mut idle: bool = true;
+
+        match socket.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                idle = false;
+                if !write_all_nonblocking(&mut channel, &buffer[..n], &stop) {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+
+        match channel.read(&mut buffer) {
+            Ok(0) => {
+                if channel.eof() {
+                    break;
+                }
+            }
+            Ok(n) => {
+                idle = false;
+                if !write_all_nonblocking(&mut socket, &buffer[..n], &stop) {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+
+        if idle {
+            thread::sleep(IDLE_SLEEP);
+        }
+    }
+
+    let _ = socket.shutdown(std::net::Shutdown::Both);
+    let _ = channel.close();
+}
+
+// The poll driven version of pump_channel, for connections that share a session with other work.
+// Whoever owns the session calls poll regularly with the session in non-blocking mode
+pub struct ChannelBridge {
+    channel: Channel,
+    socket: TcpStream,
+
+    // Data read from one side that the other side wasn't ready to take yet
+    to_channel: Vec<u8>,
+    to_socket: Vec<u8>,
+
+    socket_closed: bool,
+    channel_closed: bool,
+}
+
+impl ChannelBridge {
+    pub fn new(channel: Channel, socket: TcpStream) -> Result<Self, String> {
+        socket.set_nonblocking(true)
+            .map_err(|_| "could not make the local socket non-blocking".to_string())?;
+
+        Ok(ChannelBridge {
+            channel,
+            socket,
+            to_channel: Vec::new(),
+            to_socket: Vec::new(),
+            socket_closed: false,
+            channel_closed: false,
+        })
+    }
+
+    // For bytes already read from the socket before it was handed to the bridge
+    pub fn queue_to_channel(&mut self, data: &[u8]) {
+        self.to_channel.extend_from_slice(data);
+    }
+
+    // Moves whatever is ready in both directions, returning (bytes sent, bytes received)
+    pub fn poll(&mut self) -> (u64, u64) {
+        let mut buffer = [0u8; PUMP_BUFFER_SIZE];
+        let mut sent: u64 = 0;
+        let mut received: u64 = 0;
+
+        // Local socket -> channel
+        if self.to_channel.is_empty() && !self.socket_closed {
+            match self.socket.read(&mut buffer) {
+                Ok(0) => self.socket_closed = true,
+                Ok(n) => self.to_channel.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => self.socket_closed = true,
+            }
+        }
+
+        if !self.to_channel.is_empty() {
+            match self.channel.write(&self.to_channel) {
+                Ok(n) => {
+                    self.to_channel.drain(..n);
+                    sent += n as u64;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => self.channel_closed = true,
+            }
+        }
+
+        // Channel -> local socket
+        if self.to_socket.is_empty() && !self.channel_closed {
+            match self.channel.read(&mut buffer) {
+                Ok(0) => {
+                    if self.channel.eof() {
+                        self.channel_closed = true;
+                    }
+                }
+                Ok(n) => self.to_socket.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => self.channel_closed = true,
+            }
+        }
+
+        if !self.to_socket.is_empty() {
+            match self.socket.write(&self.to_socket) {
+                Ok(n) => {
+                    self.to_socket.drain(..n);
+                    received += n as u64;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => self.socket_closed = true,
+            }
+        }
+
+        (sent, received)
+    }
+
+    // Once either side has closed and everything it sent has been passed on
+    pub fn is_finished(&self) -> bool {
+        (self.socket_closed && self.to_channel.is_empty()) || (self.channel_closed && self.to_socket.is_empty())
+    }
+
+    pub fn close(&mut self) {
+        let _ = self.socket.shutdown(std::net::Shutdown::Both);
+        let _ = self.channel.close();
+    }
+}
+
+// libssh2 only opens one channel at a time on a session. A non-blocking open that would block is
+// left half done, and the next open of any kind carries on with it and gets its channel. So the
+// tunnels' direct-tcpip opens all go through here, and it's finished off before any blocking open
+pub struct ChannelOpener {
+    // Where the open left half done goes. Any channel there will do for whoever asks for one next
+    in_progress: Option<(String, u16)>,
+    // Whether anyone asked for the half done open since the last end_poll
+    claimed: bool,
+}
+
+impl ChannelOpener {
+    pub fn new() -> Self {
+        ChannelOpener {
+            in_progress: None,
+            claimed: false,
+        }
+    }
+
+    // Ok(None) while the server hasn't answered yet, or while an open somewhere else is half done,
+    // ask again on the next poll. The session must be in non-blocking mode
+    pub fn open_direct_tcpip(&mut self, session: &Session, host: &str, port: u16) -> Result<Option<Channel>, String> {
+        if let Some((open_host, open_port)) = &self.in_progress {
+            if open_host != host || *open_port != port {
+                return Ok(None);
+            }
+            self.claimed = true;
+        }
+
+        match session.channel_direct_tcpip(host, port, None) {
+            Ok(channel) => {
+                self.in_progress = None;
+                Ok(Some(channel))
+            }
+            Err(e) => {
+                let error: std::io::Error = e.into();
+
+                if error.kind() == ErrorKind::WouldBlock {
+                    self.in_progress = Some((host.to_string(), port));
+                    self.claimed = true;
+                    return Ok(None);
+                }
+
+                self.in_progress = None;
+                Err(error.to_string())
+            }
+        }
+    }
+
+    // Called once every tunnel has polled. An open nobody asked for again, because its connection
+    // went away, is carried on here and its channel closed, so it can't hold up the others
+    pub fn end_poll(&mut self, session: &Session) {
+        if std::mem::take(&mut self.claimed) {
+            return;
+        }
+
+        let Some((host, port)) = self.in_progress.clone() else {
+            return;
+        };
+
+        session.set_blocking(false);
+
+        if let Ok(Some(mut channel)) = self.open_direct_tcpip(session, &host, port) {
+            let _ = channel.close();
+        }
+        self.claimed = false;
+
+        session.set_blocking(true);
+    }
+
+    // Waits for any half done open and closes its channel, whoever asked for it opens another later.
+    // The session must be in blocking mode
+    pub fn finish(&mut self, session: &Session) {
+        if let Some((host, port)) = self.in_progress.take()
+            && let Ok(mut channel) = session.channel_direct_tcpip(&host, port, None) {
+            let _ = channel.close();
+        }
+        self.claimed = false;
+    }
+
+    // For a new session, which has nothing half done
+    pub fn reset(&mut self) {
+        self.in_progress = None;
+        self.claimed = false;
+    }
+}
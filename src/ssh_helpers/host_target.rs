@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+
+pub const DEFAULT_SSH_PORT: u16 = 22;
+
+// The hostname field can be any of: host, host:port, [v6addr]:port, user@host:port or a bare v6 address.
+// The port is left empty when not given so ~/.ssh/config or --port can fill it in
+#[derive(Clone, Debug)]
+pub struct HostTarget {
+    pub username: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl HostTarget {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut remaining: &str = input.trim();
+        let mut username: Option<String> = None;
+
+        if remaining.is_empty() {
+            return Err("[SSH WARN] The hostname is empty".to_string());
+        }
+
+        // Usernames can't contain an @ so the last one is the separator
+        if let Some(index) = remaining.rfind('@') {
+            let user_part: &str = &remaining[..index];
+
+            if user_part.is_empty() {
+                return Err("[SSH WARN] Nothing was given before the @ in the hostname".to_string());
+            }
+            username = Some(user_part.to_string());
+            remaining = &remaining[index + 1..];
+        }
+
+        let (host, port): (&str, Option<&str>) = if let Some(stripped) = remaining.strip_prefix('[') {
+            // Bracketed IPv6 literal, optionally followed by :port
+            let close_index = stripped.find(']')
+                .ok_or("[SSH WARN] Missing closing ] in the IPv6 address".to_string())?;
+            let after: &str = &stripped[close_index + 1..];
+
+            let port = if after.is_empty() {
+                None
+            } else if let Some(p) = after.strip_prefix(':') {
+                Some(p)
+            } else {
+                return Err("[SSH WARN] Unexpected characters after the IPv6 address".to_string());
+            };
+
+            (&stripped[..close_index], port)
+        } else if remaining.matches(':').count() > 1 {
+            // More than one colon without brackets can only be a bare IPv6 address
+            (remaining, None)
+        } else if let Some((h, p)) = remaining.split_once(':') {
+            (h, Some(p))
+        } else {
+            (remaining, None)
+        };
+
+        if host.is_empty() {
+            return Err("[SSH WARN] The hostname is empty".to_string());
+        }
+
+        let port: Option<u16> = match port {
+            Some(p) => Some(p.parse::<u16>()
+                .ok()
+                .filter(|p| *p != 0)
+                .ok_or(format!("[SSH WARN] '{}' is not a valid port", p))?),
+            None => None,
+        };
+
+        Ok(HostTarget {
+            username,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>, String> {
+        let addresses: Vec<SocketAddr> = (self.host.as_str(), self.port.unwrap_or(DEFAULT_SSH_PORT))
+            .to_socket_addrs()
+            .map_err(|_| format!("[SSH WARN] Could not resolve {}", self.host))?
+            .collect();
+
+        if addresses.is_empty() {
+            return Err(format!("[SSH WARN] No addresses were found for {}", self.host));
+        }
+
+        Ok(addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_plain_host() {
+        let target = HostTarget::parse("  example.com ").unwrap();
+
+        assert_eq!(target.host, "example.com");
+        assert_eq!(target.username, None);
+        assert_eq!(target.port, None);
+    }
+
+    #[test]
+    fn parses_user_host_and_port() {
+        let target = HostTarget::parse("alice@example.com:2222").unwrap();
+
+        assert_eq!(target.username.as_deref(), Some("alice"));
+        assert_eq!(target.host, "example.com");
+        assert_eq!(target.port, Some(2222));
+    }
+
+    #[test]
+    fn splits_the_username_at_the_last_at() {
+        let target = HostTarget::parse("alice@corp@example.com").unwrap();
+
+        assert_eq!(target.username.as_deref(), Some("alice@corp"));
+        assert_eq!(target.host, "example.com");
+    }
+
+    #[test]
+    fn parses_ipv6_addresses() {
+        let bracketed = HostTarget::parse("bob@[2001:db8::1]:2200").unwrap();
+        assert_eq!(bracketed.username.as_deref(), Some("bob"));
+        assert_eq!(bracketed.host, "2001:db8::1");
+        assert_eq!(bracketed.port, Some(2200));
+
+        let without_port = HostTarget::parse("[::1]").unwrap();
+        assert_eq!(without_port.host, "::1");
+        assert_eq!(without_port.port, None);
+
+        // Without brackets there's no telling a port apart from the address
+        let bare = HostTarget::parse("fe80::1").unwrap();
+        assert_eq!(bare.host, "fe80::1");
+        assert_eq!(bare.port, None);
+    }
+
+    #[test]
+    fn rejects_malformed_hostnames() {
+        for input in ["", "   ", "@example.com", "alice@", "[::1", "[::1]x", "[::1]:", "example.com:0", "example.com:ssh", "example.com:70000"] {
+            assert!(HostTarget::parse(input).is_err(), "{} should be rejected", input);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use ssh2::KeyboardInteractivePrompt;
+use ssh2::Prompt;
+use ssh2::Session;
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::ssh_helpers::secret::Secret;
+
+// One question from the server, and whether the answer can be shown as it's typed
+#[derive(Clone)]
+pub struct ServerPrompt {
+    pub text: String,
+    pub echo: bool,
+}
+
+// A round is everything the server asks for in a single request, it wants all the answers back at once
+struct PromptRound {
+    instructions: String,
+    prompts: Vec<ServerPrompt>,
+}
+
+// libssh2 asks for answers from inside userauth_keyboard_interactive, so that runs on its own
+// thread and waits here for the UI to collect answers over the following frames
+struct ChannelPrompter {
+    round_sender: Sender<PromptRound>,
+    answer_receiver: Receiver<Vec<String>>,
+    known_password: Option<Secret>,
+}
+
+impl KeyboardInteractivePrompt for ChannelPrompter {
+    fn prompt<'a>(&mut self, _username: &str, instructions: &str, prompts: &[Prompt<'a>]) -> Vec<String> {
+        if prompts.is_empty() {
+            return Vec::new();
+        }
+
+        // Servers usually ask for the account password first, which the login form may already have
+        if prompts.len() == 1 && !prompts[0].echo && prompts[0].text.to_lowercase().contains("password")
+            && let Some(password) = self.known_password.take() {
+            return vec![password.expose().to_string()];
+        }
+
+        let round = PromptRound {
+            instructions: instructions.to_string(),
+            prompts: prompts.iter()
+                .map(|p| ServerPrompt { text: p.text.to_string(), echo: p.echo })
+                .collect(),
+        };
+
+        // If the UI side has gone away, answering with nothing makes the server fail the login
+        if self.round_sender.send(round).is_err() {
+            return Vec::new();
+        }
+
+        self.answer_receiver.recv().unwrap_or_default()
+    }
+}
+
+pub struct KeyboardInteractiveAuth {
+    session: Session,
+    tcp_stream: TcpStream,
+
+    round_receiver: Receiver<PromptRound>,
+    answer_sender: Sender<Vec<String>>,
+    handle: JoinHandle<Result<(), String>>,
+
+    // Prompts of the current round not yet shown, and the answers collected for it so far
+    queued_prompts: VecDeque<ServerPrompt>,
+    answers: Vec<String>,
+    expected_answers: usize,
+}
+
+impl KeyboardInteractiveAuth {
+    pub fn start(session: Session, tcp_stream: TcpStream, username: String, known_password: Option<Secret>) -> Self {
+        Self::spawn(session, tcp_stream, known_password, move |thread_session, prompter| {
+            thread_session.userauth_keyboard_interactive(&username, prompter)
+                .map_err(|e| e.message().to_string())
+        })
+    }
+
+    // For servers that only take a password, like a jump host that needs its own. It's asked for
+    // through the same prompts and then sent with userauth_password
+    pub fn start_password(session: Session, tcp_stream: TcpStream, username: String, prompt_text: String) -> Self {
+        Self::spawn(session, tcp_stream, None, move |thread_session, prompter| {
+            let prompt = Prompt { text: Cow::Owned(prompt_text), echo: false };
+            let password: Secret = Secret::new(prompter.prompt(&username, "", &[prompt]).into_iter().next().unwrap_or_default());
+
+            thread_session.userauth_password(&username, password.expose())
+                .map_err(|e| e.message().to_string())
+        })
+    }
+
+    fn spawn<F>(session: Session, tcp_stream: TcpStream, known_password: Option<Secret>, authenticate: F) -> Self
+    where
+        F: FnOnce(&Session, &mut ChannelPrompter) -> Result<(), String> + Send + 'static,
+    {
+        let (round_sender, round_receiver) = mpsc::channel::<PromptRound>();
+        let (answer_sender, answer_receiver) = mpsc::channel::<Vec<String>>();
+
+        let thread_session = session.clone();
+
+        let handle = thread::spawn(move || {
+            let mut prompter = ChannelPrompter {
+                round_sender,
+                answer_receiver,
+                known_password,
+            };
+
+            authenticate(&thread_session, &mut prompter)
+        });
+
+        KeyboardInteractiveAuth {
+            session,
+            tcp_stream,
+            round_receiver,
+            answer_sender,
+            handle,
+            queued_prompts: VecDeque::new(),
+            answers: Vec::new(),
+            expected_answers: 0,
+        }
+    }
+
+    // Returns any instructions from the server alongside the next prompt that needs an answer
+    pub fn next_prompt(&mut self) -> Option<(Option<String>, ServerPrompt)> {
+        if let Some(prompt) = self.queued_prompts.pop_front() {
+            return Some((None, prompt));
+        }
+
+        // Only start a new round once the last one has been answered
+        if self.expected_answers != 0 {
+            return None;
+        }
+
+        let round = self.round_receiver.try_recv().ok()?;
+
+        self.expected_answers = round.prompts.len();
+        self.queued_prompts = round.prompts.into_iter().collect();
+
+        let instructions = if round.instructions.trim().is_empty() {
+            None
+        } else {
+            Some(round.instructions)
+        };
+
+        self.queued_prompts.pop_front().map(|prompt| (instructions, prompt))
+    }
+
+    pub fn is_waiting_for_answer(&self) -> bool {
+        self.expected_answers != 0
+    }
+
+    pub fn answer(&mut self, text: &str) {
+        if self.expected_answers == 0 {
+            return;
+        }
+
+        self.answers.push(text.to_string());
+
+        if self.answers.len() == self.expected_answers {
+            let _ = self.answer_sender.send(std::mem::take(&mut self.answers));
+            self.expected_answers = 0;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    // Blocks until the thread is done, so check is_finished first
+    pub fn finish(self) -> Result<(Session, TcpStream), String> {
+        self.handle.join()
+            .unwrap_or(Err("The authentication thread stopped unexpectedly".to_string()))?;
+
+        Ok((self.session, self.tcp_stream))
+    }
+}
@@ -0,0 +1,147 @@
+use ssh2::CheckResult;
+use ssh2::HashType;
+use ssh2::HostKeyType;
+use ssh2::KnownHostFileKind;
+use ssh2::Session;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+
+use crate::ssh_helpers::ssh_directory;
+use crate::ssh_helpers::host_target::DEFAULT_SSH_PORT;
+
+pub enum HostKeyStatus {
+    Trusted,
+    // Both carry the fingerprint of the key the server presented
+    Unknown(String),
+    Changed(String),
+}
+
+pub fn known_hosts_path() -> Option<PathBuf> {
+    ssh_directory().map(|dir| dir.join("known_hosts"))
+}
+
+// known_hosts only uses the [host]:port form for non-standard ports
+fn known_hosts_name(host: &str, port: u16) -> String {
+    if port == DEFAULT_SSH_PORT {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> Option<&'static str> {
+    match key_type {
+        HostKeyType::Rsa => Some("ssh-rsa"),
+        HostKeyType::Dss => Some("ssh-dss"),
+        HostKeyType::Ecdsa256 => Some("ecdsa-sha2-nistp256"),
+        HostKeyType::Ecdsa384 => Some("ecdsa-sha2-nistp384"),
+        HostKeyType::Ecdsa521 => Some("ecdsa-sha2-nistp521"),
+        HostKeyType::Ed25519 => Some("ssh-ed25519"),
+        HostKeyType::Unknown => None,
+    }
+}
+
+// Same format OpenSSH prints, e.g. SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s
+pub fn host_key_fingerprint(session: &Session) -> String {
+    match session.host_key_hash(HashType::Sha256) {
+        Some(hash) => format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)),
+        None => "(unavailable)".to_string(),
+    }
+}
+
+pub fn check_host_key(session: &Session, host: &str, port: u16) -> Result<HostKeyStatus, String> {
+    let (key, _key_type) = session.host_key()
+        .ok_or("[SSH ERROR] The server did not present a host key".to_string())?;
+
+    let mut known_hosts = session.known_hosts()
+        .map_err(|_| "[SSH ERROR] Could not initialise the known hosts list".to_string())?;
+
+    // A missing known_hosts file just means every host is unknown
+    if let Some(path) = known_hosts_path().filter(|path| path.exists()) {
+        known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(|_| format!("[SSH ERROR] Could not read {}", path.display()))?;
+    }
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(HostKeyStatus::Trusted),
+        CheckResult::NotFound => Ok(HostKeyStatus::Unknown(host_key_fingerprint(session))),
+        CheckResult::Mismatch => Ok(HostKeyStatus::Changed(host_key_fingerprint(session))),
+        CheckResult::Failure => Err("[SSH ERROR] Something went wrong checking the host key".to_string()),
+    }
+}
+
+// Appends rather than rewriting the file so nothing libssh2 doesn't understand is lost
+pub fn add_host_key(session: &Session, host: &str, port: u16) -> Result<PathBuf, String> {
+    let (key, key_type) = session.host_key()
+        .ok_or("[SSH ERROR] The server did not present a host key".to_string())?;
+
+    let type_name = key_type_name(key_type)
+        .ok_or("[SSH ERROR] The server's host key is of an unknown type".to_string())?;
+
+    let path = known_hosts_path()
+        .ok_or("[SSH ERROR] Could not find your home directory".to_string())?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|_| format!("[SSH ERROR] Could not create {}", parent.display()))?;
+    }
+
+    // Don't glue the new entry onto the end of a last line that has no newline
+    let needs_newline: bool = std::fs::read(&path)
+        .map(|contents| contents.last().is_some_and(|byte| *byte != b'\n'))
+        .unwrap_or(false);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|_| format!("[SSH ERROR] Could not open {}", path.display()))?;
+
+    if needs_newline {
+        writeln!(file)
+            .map_err(|_| format!("[SSH ERROR] Could not write to {}", path.display()))?;
+    }
+
+    writeln!(file, "{} {} {}", known_hosts_name(host, port), type_name, STANDARD.encode(key))
+        .map_err(|_| format!("[SSH ERROR] Could not write to {}", path.display()))?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST_KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIIrMQ+4/YsrXA150H8ta73CFyxT+0S+hUAFhWFqi+qgu";
+
+    #[test]
+    fn names_hosts_like_openssh() {
+        assert_eq!(known_hosts_name("example.com", DEFAULT_SSH_PORT), "example.com");
+        assert_eq!(known_hosts_name("example.com", 2222), "[example.com]:2222");
+        assert_eq!(known_hosts_name("2001:db8::1", 2222), "[2001:db8::1]:2222");
+    }
+
+    // What add_host_key writes has to be found again by check_host_key's lookup
+    #[test]
+    fn libssh2_finds_entries_by_port() {
+        let session = Session::new().unwrap();
+        let key: Vec<u8> = STANDARD.decode(HOST_KEY).unwrap();
+
+        let mut known_hosts = session.known_hosts().unwrap();
+        known_hosts.read_str(&format!("{} ssh-ed25519 {}", known_hosts_name("example.com", 2222), HOST_KEY), KnownHostFileKind::OpenSSH).unwrap();
+
+        assert!(matches!(known_hosts.check_port("example.com", 2222, &key), CheckResult::Match));
+        assert!(matches!(known_hosts.check_port("example.com", DEFAULT_SSH_PORT, &key), CheckResult::NotFound));
+
+        let mut known_hosts = session.known_hosts().unwrap();
+        known_hosts.read_str(&format!("{} ssh-ed25519 {}", known_hosts_name("example.com", DEFAULT_SSH_PORT), HOST_KEY), KnownHostFileKind::OpenSSH).unwrap();
+
+        assert!(matches!(known_hosts.check_port("example.com", DEFAULT_SSH_PORT, &key), CheckResult::Match));
+    }
+}
@@ -0,0 +1,21 @@
+pub mod host_target;
+pub mod known_hosts;
+pub mod keyboard_interactive;
+pub mod ssh_config;
+pub mod algorithms;
+pub mod certificate;
+pub mod key_discovery;
+pub mod secret;
+pub mod vault;
+pub mod channel_pump;
+pub mod proxy_jump;
+pub mod port_forwarding;
+pub mod socks_proxy;
+pub mod shell_channel;
+pub mod session_worker;
+
+use std::path::PathBuf;
+
+pub fn ssh_directory() -> Option<PathBuf> {
+    std::env::home_dir().map(|home| home.join(".ssh"))
+}
@@ -0,0 +1,377 @@
+use ssh2::Listener;
+use ssh2::Session;
+
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+
+use crate::ssh_helpers::channel_pump::ChannelBridge;
+use crate::ssh_helpers::channel_pump::ChannelOpener;
+
+// How many times each connection is pumped per poll, so one busy tunnel can't hold up the frame
+const MAX_PUMPS_PER_POLL: usize = 64;
+
+// Same format as ssh -L: [bind_address:]port:host:hostport
+pub struct LocalForwardSpec {
+    pub bind_address: String,
+    pub local_port: u16,
+    pub remote_host: String,
+    pub remote_port: u16,
+}
+
+impl LocalForwardSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = split_forward_spec(spec.trim());
+
+        let (bind_address, local_port, remote_host, remote_port) = match parts.as_slice() {
+            [port, host, host_port] => ("127.0.0.1", *port, *host, *host_port),
+            [bind, port, host, host_port] => (*bind, *port, *host, *host_port),
+            _ => return Err(format!("[SSH FORWARD] '{}' should look like [bind_address:]port:host:hostport", spec)),
+        };
+
+        Ok(LocalForwardSpec {
+            bind_address: bind_address.to_string(),
+            local_port: parse_port(local_port)?,
+            remote_host: remote_host.to_string(),
+            remote_port: parse_port(remote_port)?,
+        })
+    }
+}
+
+// Same format as ssh -R: [bind_address:]port:host:hostport, where port is listened on by the server
+pub struct RemoteForwardSpec {
+    pub bind_address: Option<String>,
+    pub remote_port: u16,
+    pub local_host: String,
+    pub local_port: u16,
+}
+
+impl RemoteForwardSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = split_forward_spec(spec.trim());
+
+        let (bind_address, remote_port, local_host, local_port) = match parts.as_slice() {
+            [port, host, host_port] => (None, *port, *host, *host_port),
+            [bind, port, host, host_port] => (Some(bind.to_string()), *port, *host, *host_port),
+            _ => return Err(format!("[SSH FORWARD] '{}' should look like [bind_address:]port:host:hostport", spec)),
+        };
+
+        Ok(RemoteForwardSpec {
+            bind_address,
+            remote_port: parse_port(remote_port)?,
+            local_host: local_host.to_string(),
+            local_port: parse_port(local_port)?,
+        })
+    }
+}
+
+pub fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>()
+        .map_err(|_| format!("[SSH FORWARD] '{}' is not a valid port", port))
+}
+
+// Splits on colons outside of [] so IPv6 addresses can be given in brackets
+pub fn split_forward_spec(spec: &str) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut depth: i32 = 0;
+    let mut start: usize = 0;
+
+    for (i, c) in spec.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ':' if depth == 0 => {
+                parts.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&spec[start..]);
+
+    parts.iter()
+        .map(|part| part.trim_start_matches('[').trim_end_matches(']'))
+        .collect()
+}
+
+// 1536 -> "1.5 KB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value: f64 = bytes as f64;
+    let mut unit: usize = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// Moves data for every connection and drops the finished ones, returning (bytes sent, bytes received).
+// The session must be in non-blocking mode
+pub fn pump_bridges(connections: &mut Vec<ChannelBridge>) -> (u64, u64) {
+    let mut total_sent: u64 = 0;
+    let mut total_received: u64 = 0;
+
+    for bridge in connections.iter_mut() {
+        for _ in 0..MAX_PUMPS_PER_POLL {
+            let (sent, received) = bridge.poll();
+            total_sent += sent;
+            total_received += received;
+
+            if sent == 0 && received == 0 {
+                break;
+            }
+        }
+    }
+
+    connections.retain_mut(|bridge| {
+        if bridge.is_finished() {
+            bridge.close();
+            false
+        } else {
+            true
+        }
+    });
+
+    (total_sent, total_received)
+}
+
+// Listens locally, and sends each accepted connection to remote_host:remote_port from the server
+pub struct LocalForward {
+    pub local_address: SocketAddr,
+    pub remote_host: String,
+    pub remote_port: u16,
+
+    listener: TcpListener,
+    // Accepted connections waiting on the server to open their channel
+    pending: Vec<(TcpStream, SocketAddr)>,
+    connections: Vec<ChannelBridge>,
+
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl LocalForward {
+    pub fn open(spec: &LocalForwardSpec) -> Result<Self, String> {
+        let address: SocketAddr = (spec.bind_address.as_str(), spec.local_port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or(format!("[SSH FORWARD] Could not resolve {}", spec.bind_address))?;
+
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("[SSH FORWARD] Could not listen on {} ({})", address, e))?;
+
+        listener.set_nonblocking(true)
+            .map_err(|_| "[SSH FORWARD] Could not make the listener non-blocking".to_string())?;
+
+        Ok(LocalForward {
+            local_address: listener.local_addr().unwrap_or(address),
+            remote_host: spec.remote_host.clone(),
+            remote_port: spec.remote_port,
+            listener,
+            pending: Vec::new(),
+            connections: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+        })
+    }
+
+    // Accepts new connections, opens their channels and moves data for the existing ones, returns
+    // lines for the logger. The session must be in blocking mode, it is only made non-blocking in here
+    pub fn poll(&mut self, session: &Session, opener: &mut ChannelOpener) -> Vec<String> {
+        let mut log_lines: Vec<String> = Vec::new();
+
+        // Stops at WouldBlock, once nobody else is waiting to connect
+        while let Ok(accepted) = self.listener.accept() {
+            self.pending.push(accepted);
+        }
+
+        session.set_blocking(false);
+
+        // Opening a channel takes a round trip to the server, so a connection can wait a few polls for one
+        for (socket, peer) in std::mem::take(&mut self.pending) {
+            let opened = match opener.open_direct_tcpip(session, &self.remote_host, self.remote_port) {
+                Ok(None) => {
+                    self.pending.push((socket, peer));
+                    continue;
+                }
+                Ok(Some(channel)) => ChannelBridge::new(channel, socket),
+                Err(message) => Err(message),
+            };
+
+            match opened {
+                Ok(bridge) => self.connections.push(bridge),
+                Err(message) => log_lines.push(format!("[SSH FORWARD] {} -> {}:{} refused for {}: {}", self.local_address, self.remote_host, self.remote_port, peer, message)),
+            }
+        }
+
+        let (sent, received) = pump_bridges(&mut self.connections);
+        self.bytes_sent += sent;
+        self.bytes_received += received;
+
+        session.set_blocking(true);
+
+        log_lines
+    }
+
+    pub fn describe(&self) -> String {
+        format!("-L {} -> {}:{}  {} open  {} up / {} down",
+            self.local_address,
+            self.remote_host,
+            self.remote_port,
+            self.connections.len() + self.pending.len(),
+            format_bytes(self.bytes_sent),
+            format_bytes(self.bytes_received))
+    }
+
+    pub fn close(&mut self) {
+        for bridge in &mut self.connections {
+            bridge.close();
+        }
+        self.connections.clear();
+        self.pending.clear();
+    }
+}
+
+// The server listens on remote_port, and each connection it accepts is sent to local_host:local_port
+pub struct RemoteForward {
+    pub remote_port: u16,
+    pub local_host: String,
+    pub local_port: u16,
+
+    // Dropping the listener asks the server to stop listening
+    listener: Listener,
+    connections: Vec<ChannelBridge>,
+
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl RemoteForward {
+    // The session must be in blocking mode
+    pub fn open(session: &Session, spec: &RemoteForwardSpec) -> Result<Self, String> {
+        let (listener, bound_port) = session.channel_forward_listen(spec.remote_port, spec.bind_address.as_deref(), None)
+            .map_err(|e| format!("[SSH FORWARD] The server would not listen on port {} ({})", spec.remote_port, e.message()))?;
+
+        Ok(RemoteForward {
+            // Asking for port 0 lets the server pick one
+            remote_port: bound_port,
+            local_host: spec.local_host.clone(),
+            local_port: spec.local_port,
+            listener,
+            connections: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+        })
+    }
+
+    // Same as LocalForward::poll, but new connections come from the server
+    pub fn poll(&mut self, session: &Session) -> Vec<String> {
+        let mut log_lines: Vec<String> = Vec::new();
+
+        session.set_blocking(false);
+
+        // Stops at an error, which is usually just that nothing is waiting
+        while let Ok(channel) = self.listener.accept() {
+            let opened = TcpStream::connect((self.local_host.as_str(), self.local_port))
+                .map_err(|e| e.to_string())
+                .and_then(|socket| ChannelBridge::new(channel, socket));
+
+            match opened {
+                Ok(bridge) => self.connections.push(bridge),
+                Err(message) => log_lines.push(format!("[SSH FORWARD] remote port {} could not reach {}:{}: {}", self.remote_port, self.local_host, self.local_port, message)),
+            }
+        }
+
+        let (sent, received) = pump_bridges(&mut self.connections);
+        self.bytes_sent += sent;
+        self.bytes_received += received;
+
+        session.set_blocking(true);
+
+        log_lines
+    }
+
+    pub fn describe(&self) -> String {
+        format!("-R remote:{} -> {}:{}  {} open  {} up / {} down",
+            self.remote_port,
+            self.local_host,
+            self.local_port,
+            self.connections.len(),
+            format_bytes(self.bytes_sent),
+            format_bytes(self.bytes_received))
+    }
+
+    pub fn close(&mut self) {
+        for bridge in &mut self.connections {
+            bridge.close();
+        }
+        self.connections.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_specs_on_colons() {
+        assert_eq!(split_forward_spec("8080:localhost:80"), vec!["8080", "localhost", "80"]);
+        assert_eq!(split_forward_spec("0.0.0.0:8080:db:5432"), vec!["0.0.0.0", "8080", "db", "5432"]);
+        assert_eq!(split_forward_spec("1080"), vec!["1080"]);
+    }
+
+    #[test]
+    fn keeps_bracketed_ipv6_addresses_whole() {
+        assert_eq!(split_forward_spec("[::1]:8080:[2001:db8::1]:22"), vec!["::1", "8080", "2001:db8::1", "22"]);
+    }
+
+    #[test]
+    fn parses_local_forwards() {
+        let spec = LocalForwardSpec::parse("8080:localhost:80").unwrap();
+        assert_eq!(spec.bind_address, "127.0.0.1");
+        assert_eq!(spec.local_port, 8080);
+        assert_eq!(spec.remote_host, "localhost");
+        assert_eq!(spec.remote_port, 80);
+
+        let bound = LocalForwardSpec::parse("[::1]:8080:[2001:db8::1]:22").unwrap();
+        assert_eq!(bound.bind_address, "::1");
+        assert_eq!(bound.remote_host, "2001:db8::1");
+    }
+
+    #[test]
+    fn parses_remote_forwards() {
+        let spec = RemoteForwardSpec::parse("9000:localhost:3000").unwrap();
+        assert_eq!(spec.bind_address, None);
+        assert_eq!(spec.remote_port, 9000);
+        assert_eq!(spec.local_host, "localhost");
+        assert_eq!(spec.local_port, 3000);
+
+        let bound = RemoteForwardSpec::parse("0.0.0.0:9000:localhost:3000").unwrap();
+        assert_eq!(bound.bind_address.as_deref(), Some("0.0.0.0"));
+    }
+
+    #[test]
+    fn rejects_bad_forwards() {
+        for spec in ["", "8080", "8080:localhost", "http:localhost:80", "8080:localhost:99999", "a:b:c:d:e"] {
+            assert!(LocalForwardSpec::parse(spec).is_err(), "{} should be rejected", spec);
+            assert!(RemoteForwardSpec::parse(spec).is_err(), "{} should be rejected", spec);
+        }
+    }
+
+    #[test]
+    fn formats_byte_counts() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GB");
+    }
+}
@@ -0,0 +1,108 @@
+use ssh2::Session;
+
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::ssh_helpers::channel_pump::pump_channel;
+use crate::ssh_helpers::host_target::HostTarget;
+
+// ProxyJump style list: user@jump1:port,jump2 where "none" means no jumps
+pub fn parse_jump_list(input: &str) -> Result<Vec<HostTarget>, String> {
+    let input: &str = input.trim();
+
+    if input.is_empty() || input.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+
+    input.split(',')
+        .map(|hop| HostTarget::parse(hop.trim()))
+        .collect()
+}
+
+// ssh2 sessions need a real socket, so each hop is bridged to the next through a loopback
+// connection whose other end is pumped into a direct-tcpip channel of the hop's session
+pub struct JumpTunnel {
+    // Kept so the hop stays alive for as long as the tunnel is in use
+    _session: Session,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl JumpTunnel {
+    // Returns the local end of the tunnel, which can be given to the next Session
+    pub fn open(session: Session, host: &str, port: u16) -> Result<(TcpStream, JumpTunnel), String> {
+        let channel = session.channel_direct_tcpip(host, port, None)
+            .map_err(|e| format!("could not open a tunnel to {}:{} ({})", host, port, e.message()))?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|_| "could not open a local socket for the tunnel".to_string())?;
+        let local_address = listener.local_addr()
+            .map_err(|_| "could not open a local socket for the tunnel".to_string())?;
+
+        let local_stream = TcpStream::connect(local_address)
+            .map_err(|_| "could not connect to the local end of the tunnel".to_string())?;
+        let (tunnel_stream, _) = listener.accept()
+            .map_err(|_| "could not accept the local end of the tunnel".to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_session = session.clone();
+
+        // From here on only the pump thread touches this hop's session
+        let handle = thread::spawn(move || {
+            thread_session.set_blocking(false);
+            pump_channel(channel, tunnel_stream, thread_stop);
+        });
+
+        Ok((local_stream, JumpTunnel {
+            _session: session,
+            stop,
+            handle: Some(handle),
+        }))
+    }
+}
+
+impl Drop for JumpTunnel {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_hop() {
+        let hops: Vec<HostTarget> = parse_jump_list("alice@bastion:2222, [2001:db8::1]:2200,gateway").unwrap();
+
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0].username.as_deref(), Some("alice"));
+        assert_eq!((hops[0].host.as_str(), hops[0].port), ("bastion", Some(2222)));
+        assert_eq!((hops[1].host.as_str(), hops[1].port), ("2001:db8::1", Some(2200)));
+        assert_eq!((hops[2].host.as_str(), hops[2].port), ("gateway", None));
+        assert_eq!(hops[2].username, None);
+    }
+
+    #[test]
+    fn none_means_no_jumps() {
+        assert!(parse_jump_list("").unwrap().is_empty());
+        assert!(parse_jump_list("  NONE ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_empty_and_bad_hops() {
+        for input in ["bastion,,target", "bastion,", ",bastion", "bastion:0", "alice@", "[::1"] {
+            assert!(parse_jump_list(input).is_err(), "{} should be rejected", input);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+use zeroize::Zeroizing;
+
+// Environment variables a passphrase or the program to ask for one can come from
+pub const PASSPHRASE_VARIABLE: &str = "TRSSH_PASSPHRASE";
+pub const ASKPASS_VARIABLE: &str = "SSH_ASKPASS";
+
+// Passwords, passphrases and pasted keys. The memory is wiped once the last copy is dropped, and
+// it never shows up in debug output
+#[derive(Clone, Default)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(Zeroizing::new(value))
+    }
+
+    pub fn expose(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(hidden)")
+    }
+}
+
+// Asks without echoing when run from a terminal, otherwise takes the first line piped in
+pub fn read_secret_from_stdin(prompt: &str) -> Result<Secret, String> {
+    if std::io::stdin().is_terminal() {
+        return rpassword::prompt_password(prompt)
+            .map(Secret::new)
+            .map_err(|_| "[SSH ERROR] Could not read the passphrase from the terminal".to_string());
+    }
+
+    let mut line: Zeroizing<String> = Zeroizing::new(String::new());
+
+    std::io::stdin().lock().read_line(&mut line)
+        .map_err(|_| "[SSH ERROR] Could not read the passphrase from stdin".to_string())?;
+
+    Ok(Secret::new(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+// Like ssh's SSH_ASKPASS, the program gets the prompt as its argument and prints the answer
+pub fn read_secret_from_askpass(program: &Path, prompt: &str) -> Result<Secret, String> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|_| format!("[SSH ERROR] Could not run the askpass program {}", program.display()))?;
+
+    let stdout: Zeroizing<Vec<u8>> = Zeroizing::new(output.stdout);
+    let answer: Zeroizing<String> = Zeroizing::new(String::from_utf8_lossy(&stdout).to_string());
+
+    if !output.status.success() {
+        return Err(format!("[SSH ERROR] The askpass program {} was cancelled", program.display()));
+    }
+
+    Ok(Secret::new(answer.trim_end_matches(['\r', '\n']).to_string()))
+}
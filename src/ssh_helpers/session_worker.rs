@@ -0,0 +1,2232 @@
+use ssh2::Session;
+use std::io::prelude::*;
+
+use std::net::TcpStream;
+use std::path::Path;
+use std::fs;
+use std::fs::File;
+use std::time::Duration;
+use std::time::Instant;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use crate::ssh_helpers::host_target::HostTarget;
+use crate::ssh_helpers::host_target::DEFAULT_SSH_PORT;
+use crate::ssh_helpers::known_hosts::HostKeyStatus;
+use crate::ssh_helpers::known_hosts::check_host_key;
+use crate::ssh_helpers::known_hosts::add_host_key;
+use crate::ssh_helpers::keyboard_interactive::KeyboardInteractiveAuth;
+use crate::ssh_helpers::keyboard_interactive::ServerPrompt;
+use crate::ssh_helpers::ssh_config::SshConfig;
+use crate::ssh_helpers::ssh_config::ResolvedHost;
+use crate::ssh_helpers::ssh_config::default_config_path;
+use crate::ssh_helpers::proxy_jump::JumpTunnel;
+use crate::ssh_helpers::proxy_jump::parse_jump_list;
+use crate::ssh_helpers::channel_pump::ChannelOpener;
+use crate::ssh_helpers::port_forwarding::LocalForward;
+use crate::ssh_helpers::port_forwarding::LocalForwardSpec;
+use crate::ssh_helpers::port_forwarding::RemoteForward;
+use crate::ssh_helpers::port_forwarding::RemoteForwardSpec;
+use crate::ssh_helpers::port_forwarding::parse_port;
+use crate::ssh_helpers::socks_proxy::SocksProxy;
+use crate::ssh_helpers::socks_proxy::SocksSpec;
+use crate::ssh_helpers::shell_channel::ShellChannel;
+use crate::ssh_helpers::shell_channel::CommandResult;
+use crate::ssh_helpers::shell_channel::ShellProfile;
+use crate::ssh_helpers::shell_channel::shell_quote;
+use crate::ssh_helpers::certificate::UserCertificate;
+use crate::ssh_helpers::certificate::CERTIFICATE_WARNING_SECONDS;
+use crate::ssh_helpers::certificate::certificate_path_for;
+use crate::ssh_helpers::certificate::format_duration;
+use crate::ssh_helpers::key_discovery::discover_identities;
+use crate::ssh_helpers::key_discovery::looks_like_public_key;
+use crate::ssh_helpers::key_discovery::is_encrypted_key;
+use crate::ssh_helpers::secret::Secret;
+use crate::ssh_helpers::secret::read_secret_from_askpass;
+use crate::ssh_helpers::vault::UnlockedVault;
+use crate::ssh_helpers::algorithms::AlgorithmPreferences;
+use crate::ssh_helpers::algorithms::describe_negotiated;
+
+// How long to wait for a TCP connection and then for the SSH handshake before giving up, so a
+// dead host doesn't hold up the worker indefinitely
+pub const DEFAULT_CONNECT_TIMEOUT_SECONDS: u64 = 10;
+
+// How long an SFTP read or write can stall before the transfer is given up on
+pub const DEFAULT_TRANSFER_TIMEOUT_SECONDS: u64 = 30;
+
+// Commands typed in can run for as long as they like unless --command-timeout is set, but the ones
+// the worker runs for itself, like pwd, are always given up on after this long
+const HELPER_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Seconds between keepalives unless --keepalive says otherwise, 0 turns them off
+pub const DEFAULT_KEEPALIVE_SECONDS: u32 = 30;
+
+// --reconnect tries this many times, doubling the wait each time
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+const FIRST_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// An interrupted command that is still running after this long has its shell closed instead
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+// How long the worker waits for a job before servicing the tunnels again
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// In the event of a command (like 'cat') which displays too much data, this is a good failsafe
+const MAX_LOGGER_LINE_LENGTH: usize = 99999;
+
+// libssh2 doesn't export its error numbers through ssh2, this is LIBSSH2_ERROR_TIMEOUT
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+
+// LIBSSH2_ERROR_KEX_FAILURE, returned when the server and client have no algorithm in common
+const LIBSSH2_ERROR_KEX_FAILURE: i32 = -5;
+
+// Custom Error codes used to display points of failure and handle errors in a more syntaxically
+// attractive manner
+pub enum HandshakeErrorCode {
+    AddressFail(String),
+    MissingUsername,
+    TimeoutFail,
+    // The algorithm lists couldn't be used
+    AlgorithmFail(String),
+    // Carries what was offered to a server that agreed to none of it
+    NegotiationFail(Vec<String>),
+    HostKeyCheckFail(String),
+    // Carries the fingerprint the server presented
+    HostKeyMismatch(String),
+    HostKeyRejected,
+    // The jump host, and why it couldn't be used
+    JumpFail(String, String),
+    AgentUnreachable,
+    KeyboardInteractiveFail(String),
+    // Carries how many identities the agent offered
+    AgentAuthFail(usize),
+    TcpFail,
+    SessionFail,
+    HandshakeFail,
+    LoginAuthFail,
+    SessionAuthFail,
+}
+
+// A jump host, and what it logs in with
+struct JumpHop {
+    target: HostTarget,
+    identity_files: Vec<String>,
+    algorithms: AlgorithmPreferences,
+}
+
+// How far a hop got with its own credentials
+enum HopLogin {
+    LoggedIn(Session),
+    AwaitingPrompts(KeyboardInteractiveAuth),
+}
+
+// A jumped connection waiting on one of its hops' prompts, picked up again once they're answered
+struct PendingJump {
+    hops: Vec<JumpHop>,
+    // The hop being logged into
+    hop_index: usize,
+    tunnels: Vec<JumpTunnel>,
+    target: HostTarget,
+    algorithms: AlgorithmPreferences,
+    auth_method: AuthMethod,
+}
+
+// Which way the login form asked to authenticate
+#[derive(Clone, Copy, PartialEq)]
+pub enum AuthMethod {
+    Password,
+    KeyFile,
+    Agent,
+}
+
+// A handshake can finish without logging in if the host needs confirming first
+pub enum HandshakeStatus {
+    LoggedIn,
+    AwaitingHostKey(String),
+    AwaitingPrompts,
+}
+
+// Requested by the tunnel buttons, carried out using the forward textbox
+#[derive(Clone, Copy)]
+pub enum ForwardAction {
+    AddLocal,
+    AddRemote,
+    Stop,
+    ToggleSocks,
+}
+
+// Everything from the command line that changes how a connection is made
+#[derive(Clone)]
+pub struct ConnectionSettings {
+    // --port, used when the hostname field doesn't specify a port
+    pub default_port: Option<u16>,
+    // --config, otherwise ~/.ssh/config is used if it exists
+    pub config_path: Option<PathBuf>,
+    // --connect-timeout, otherwise the config's ConnectTimeout
+    pub connect_timeout: Option<Duration>,
+    // --command-timeout, otherwise the config's TrsshCommandTimeout. Zero turns it off
+    pub command_timeout: Option<Duration>,
+    // --transfer-timeout, otherwise the config's TrsshTransferTimeout
+    pub transfer_timeout: Option<Duration>,
+    // --socks, started automatically after logging in
+    pub socks_spec: Option<String>,
+    // --keepalive, how often to check the connection is still there
+    pub keepalive_interval: u32,
+    // --reconnect, log back in by itself when the connection drops
+    pub auto_reconnect: bool,
+    // --env NAME=value, set in every shell along with the config's SetEnv
+    pub environment: Vec<String>,
+    // --startup-command, run in every shell after the config's startup commands
+    pub startup_commands: Vec<String>,
+    // --shell-setup, replaces the line that turns the shell's prompts off
+    pub shell_setup: Option<String>,
+    // --certificate, otherwise the config's CertificateFile or the key's -cert.pub
+    pub certificate_path: Option<PathBuf>,
+    // From --passphrase-stdin or TRSSH_PASSPHRASE, used when the passphrase box is left empty
+    pub passphrase: Option<Secret>,
+    // --askpass or SSH_ASKPASS, run when an encrypted key has no passphrase
+    pub askpass: Option<PathBuf>,
+    // --kex-algorithms, --host-key-algorithms, --ciphers and --macs, each winning over the config's
+    pub algorithms: AlgorithmPreferences,
+}
+
+impl ConnectionSettings {
+    pub fn new() -> Self {
+        ConnectionSettings {
+            default_port: None,
+            config_path: None,
+            connect_timeout: None,
+            command_timeout: None,
+            transfer_timeout: None,
+            socks_spec: None,
+            keepalive_interval: DEFAULT_KEEPALIVE_SECONDS,
+            auto_reconnect: false,
+            environment: Vec::new(),
+            startup_commands: Vec::new(),
+            shell_setup: None,
+            certificate_path: None,
+            passphrase: None,
+            askpass: None,
+            algorithms: AlgorithmPreferences::default(),
+        }
+    }
+}
+
+// The login form's fields, taken when the Login button was pressed
+#[derive(Clone)]
+pub struct LoginRequest {
+    pub hostname: String,
+    pub username: String,
+    pub password: Secret,
+    pub public_key: String,
+    pub private_key: String,
+    pub passphrase: Secret,
+    // A key pasted in with the Paste Key button, used instead of the private key path
+    pub private_key_data: Option<Secret>,
+    pub jump_hosts: String,
+    pub auth_method: AuthMethod,
+}
+
+// Sent from the UI, carried out in order on the worker thread
+pub enum SessionJob {
+    Configure(ConnectionSettings),
+    Login(LoginRequest),
+    // Logs in again with the last login's details
+    Reconnect,
+    // Logs out and closes everything that was using the connection
+    Disconnect,
+    HostKeyDecision(bool),
+    AnswerPrompt(String),
+    Forward(ForwardAction, String),
+    ExecuteCommand(String),
+    // Ctrl+C for the running command
+    CancelCommand,
+    DownloadFile(String),
+    UploadFile(String),
+    // Directories to create, then files to upload, both relative to the remote working directory
+    UploadDirectory(Vec<String>, Vec<String>),
+    // Derives the vault's key from the master password, which is too slow to do in a frame
+    UnlockVault(PathBuf, Secret),
+    // Makes a new vault with the master password, once the UI has had it typed twice
+    CreateVault(Secret),
+}
+
+// Sent back to the UI, which drains them every frame
+pub enum SessionEvent {
+    Log(String),
+    // Mirrors the worker's state so the UI knows what it's allowed to ask for
+    Status { logged_in: bool, still_valid: bool },
+    Prompt(ServerPrompt),
+    PromptsFinished,
+    Tunnels(Vec<String>),
+    // The shell's working directory, None while there isn't a shell
+    WorkingDirectory(Option<String>),
+    VaultUnlocked(Result<UnlockedVault, String>),
+}
+
+// The UI's end of the worker thread
+pub struct WorkerHandle {
+    jobs: Sender<SessionJob>,
+    events: Receiver<SessionEvent>,
+}
+
+impl WorkerHandle {
+    pub fn spawn() -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<SessionJob>();
+        let (event_sender, event_receiver) = mpsc::channel::<SessionEvent>();
+
+        thread::spawn(move || {
+            SessionWorker::new(event_sender).run(job_receiver);
+        });
+
+        WorkerHandle {
+            jobs: job_sender,
+            events: event_receiver,
+        }
+    }
+
+    pub fn send(&self, job: SessionJob) {
+        let _ = self.jobs.send(job);
+    }
+
+    // Everything the worker has sent since the last call
+    pub fn drain_events(&self) -> Vec<SessionEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+// Owns the connection. Everything that can block on the network happens here rather than in a frame
+struct SessionWorker {
+    events: Sender<SessionEvent>,
+    settings: ConnectionSettings,
+
+    remote_server: String,
+    port: u16,
+    // From the settings or the host's config, for the login in progress
+    connect_timeout: Duration,
+    // Interrupts commands that run longer, None lets them run forever
+    command_timeout: Option<Duration>,
+    // For each SFTP read and write
+    transfer_timeout: Duration,
+    username: String,
+    // Password auth
+    password: Secret,
+
+    // SSH Key Auth
+    passphrase: Secret,
+    public_key: String,
+    private_key: String,
+    private_key_data: Option<Secret>,
+
+    // These are used by ssh2
+    tcp_stream: Option<TcpStream>,
+    session: Option<Session>,
+
+    // A connection that has handshaken with an unknown host, waiting for the user to trust it
+    pending_connection: Option<(Session, TcpStream, AuthMethod)>,
+
+    // Keyboard-interactive logins in progress, and whether its current prompt has been sent to the UI
+    keyboard_interactive: Option<KeyboardInteractiveAuth>,
+    prompt_sent: bool,
+
+    // Informational lines from the last login attempt, logged before its result
+    notices: Vec<String>,
+
+    // Comma separated jump hosts from the login form, and the tunnels through them once connected
+    jump_hosts: String,
+    jump_tunnels: Vec<JumpTunnel>,
+    // Set while keyboard_interactive is logging into a hop rather than the final host
+    pending_jump: Option<PendingJump>,
+
+    // -L style forwards, serviced every time round the loop while logged in
+    local_forwards: Vec<LocalForward>,
+    // -R style forwards, the server listens and the connections come back here
+    remote_forwards: Vec<RemoteForward>,
+    // -D style proxy, started from --socks after logging in or by the SOCKS button
+    socks_proxy: Option<SocksProxy>,
+    socks_autostart: bool,
+    // Opens the channels for the tunnels' connections without waiting on the server
+    channel_opener: ChannelOpener,
+
+    // Keys from IdentityFile lines, tried in order when no private key path was typed in
+    identity_files: Vec<String>,
+
+    // From --certificate and CertificateFile, tried with each key before the key's own -cert.pub
+    certificate_files: Vec<PathBuf>,
+    // The certificate the login should be using, and whether its expiry has been warned about
+    certificate: Option<UserCertificate>,
+    certificate_warned: bool,
+
+    // This is a variable which is checked before this structure runs code - if the tcp stream
+    // fails, to prevent the whole program closing this will block it
+    session_still_valid: bool,
+
+    have_logged_in: bool,
+
+    // The persistent shell commands are typed into, and when its running command was interrupted
+    shell: Option<ShellChannel>,
+    interrupted_at: Option<Instant>,
+    // How each new shell is set up, for the login in progress
+    shell_profile: ShellProfile,
+
+    // The shell's $PWD, and where it really is once symlinks are resolved. Transfers go here
+    shell_directory: Option<String>,
+    remote_directory: Option<String>,
+
+    // Kept so a dropped connection can be logged back into, along with the tunnels it had open
+    last_login: Option<LoginRequest>,
+    restore_forwards: Vec<(ForwardAction, String)>,
+    next_keepalive: Instant,
+    reconnect_attempts: u32,
+    next_reconnect: Option<Instant>,
+
+    // What the UI was last told, so it's only sent again when it changes
+    last_status: Option<(bool, bool)>,
+    last_tunnels: Vec<String>,
+}
+
+impl SessionWorker {
+    fn new(events: Sender<SessionEvent>) -> Self {
+        SessionWorker {
+            events,
+            settings: ConnectionSettings::new(),
+
+            remote_server: String::new(),
+            port: DEFAULT_SSH_PORT,
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECONDS),
+            command_timeout: None,
+            transfer_timeout: Duration::from_secs(DEFAULT_TRANSFER_TIMEOUT_SECONDS),
+            username: String::new(),
+
+            // For username/password login
+            password: Secret::default(),
+
+            // For Key
+            passphrase: Secret::default(),
+            public_key: String::new(),
+            private_key: String::new(),
+            private_key_data: None,
+
+            tcp_stream: None,
+            session: None,
+
+            pending_connection: None,
+
+            keyboard_interactive: None,
+            prompt_sent: false,
+
+            notices: Vec::<String>::new(),
+
+            jump_hosts: String::new(),
+            jump_tunnels: Vec::<JumpTunnel>::new(),
+            pending_jump: None,
+
+            local_forwards: Vec::<LocalForward>::new(),
+            remote_forwards: Vec::<RemoteForward>::new(),
+            socks_proxy: None,
+            socks_autostart: false,
+            channel_opener: ChannelOpener::new(),
+            identity_files: Vec::<String>::new(),
+
+            certificate_files: Vec::<PathBuf>::new(),
+            certificate: None,
+            certificate_warned: false,
+
+            have_logged_in: false,
+            session_still_valid: true,
+
+            shell: None,
+            interrupted_at: None,
+            shell_profile: ShellProfile::new(),
+
+            shell_directory: None,
+            remote_directory: None,
+
+            last_login: None,
+            restore_forwards: Vec::<(ForwardAction, String)>::new(),
+            next_keepalive: Instant::now(),
+            reconnect_attempts: 0,
+            next_reconnect: None,
+
+            last_status: None,
+            last_tunnels: Vec::<String>::new(),
+        }
+    }
+
+    // Runs until the UI drops its handle
+    fn run(mut self, jobs: Receiver<SessionJob>) {
+        loop {
+            match jobs.recv_timeout(WORKER_POLL_INTERVAL) {
+                Ok(job) => self.handle_job(job),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            self.poll_keyboard_interactive();
+            self.poll_command();
+            self.poll_keepalive();
+            self.poll_certificate();
+            self.poll_reconnect();
+            self.poll_forwards();
+
+            self.send_state();
+        }
+    }
+
+    fn log(&self, line: &str) {
+        let _ = self.events.send(SessionEvent::Log(line.to_string()));
+    }
+
+    fn send_state(&mut self) {
+        let status: (bool, bool) = (self.have_logged_in, self.session_still_valid);
+
+        if self.last_status != Some(status) {
+            self.last_status = Some(status);
+            let _ = self.events.send(SessionEvent::Status { logged_in: status.0, still_valid: status.1 });
+        }
+
+        let tunnels: Vec<String> = self.describe_tunnels();
+
+        if tunnels != self.last_tunnels {
+            self.last_tunnels = tunnels.clone();
+            let _ = self.events.send(SessionEvent::Tunnels(tunnels));
+        }
+    }
+
+    fn handle_job(&mut self, job: SessionJob) {
+        match job {
+            SessionJob::Configure(settings) => {
+                if settings.socks_spec.is_some() && settings.socks_spec != self.settings.socks_spec {
+                    self.socks_autostart = true;
+                }
+                self.settings = settings;
+            }
+            SessionJob::Login(request) => {
+                // Logging in again switches hosts rather than leaving the old connection behind
+                if self.have_logged_in || self.pending_connection.is_some() || self.keyboard_interactive.is_some() {
+                    self.disconnect();
+                }
+
+                // A new login doesn't bring back the tunnels of a connection that dropped
+                self.restore_forwards.clear();
+                self.next_reconnect = None;
+
+                let ssh_result = self.make_ssh_handshake(request);
+                self.log_handshake_result(ssh_result);
+            }
+            SessionJob::Reconnect => {
+                if self.have_logged_in {
+                    self.log("[SSH INFO] Already connected, there is nothing to reconnect");
+                } else if self.last_login.is_none() {
+                    self.log("[SSH INFO] There is nothing to reconnect to, please log in first");
+                } else {
+                    self.reconnect_attempts = 0;
+                    self.next_reconnect = None;
+                    self.attempt_reconnect();
+                }
+            }
+            SessionJob::Disconnect => self.disconnect(),
+            SessionJob::HostKeyDecision(trust) => {
+                match self.resolve_pending_host_key(trust) {
+                    Some(ssh_result) => self.log_handshake_result(ssh_result),
+                    None => self.log("[SSH INFO] There is no host waiting to be trusted"),
+                }
+            }
+            SessionJob::AnswerPrompt(text) => {
+                match self.keyboard_interactive.as_mut() {
+                    Some(auth) if auth.is_waiting_for_answer() => {
+                        auth.answer(&text);
+                        self.prompt_sent = false;
+                    }
+                    _ => self.log("[SSH INFO] The server isn't waiting on an answer"),
+                }
+            }
+            SessionJob::Forward(action, spec) => {
+                let line: String = match action {
+                    ForwardAction::AddLocal => self.add_local_forward(&spec),
+                    ForwardAction::AddRemote => self.add_remote_forward(&spec),
+                    ForwardAction::Stop => self.stop_forwards(&spec),
+                    ForwardAction::ToggleSocks => self.toggle_socks(&spec),
+                };
+                self.log(&line);
+            }
+            SessionJob::ExecuteCommand(command) => self.run_command(&command),
+            SessionJob::CancelCommand => self.cancel_command(),
+            SessionJob::DownloadFile(filename) => {
+                let result = self.remote_directory()
+                    .and_then(|directory| self.with_transfer_timeout(|worker| worker.download_file(&filename, &directory)));
+
+                match result {
+                    Ok(filepath) => self.log(&format!("Downloaded file to {}", filepath)),
+                    Err(e) => self.log(&e),
+                }
+            }
+            SessionJob::UploadFile(filename) => {
+                let result = self.remote_directory()
+                    .and_then(|directory| self.with_transfer_timeout(|worker| worker.upload_file(&filename, &directory)));
+
+                match result {
+                    Ok(filepath) => self.log(&format!("Uploaded file to {}", filepath)),
+                    Err(e) => self.log(&e),
+                }
+            }
+            SessionJob::UploadDirectory(directories, files) => {
+                self.with_transfer_timeout(|worker| worker.upload_directory(&directories, &files));
+            }
+            SessionJob::UnlockVault(path, master_password) => {
+                self.unlock_vault(move || UnlockedVault::open(&path, &master_password));
+            }
+            SessionJob::CreateVault(master_password) => {
+                self.unlock_vault(move || UnlockedVault::create(&master_password));
+            }
+        }
+    }
+
+    // Has its own thread so the connection's tunnels and keepalives aren't held up while the key is derived
+    fn unlock_vault(&self, unlock: impl FnOnce() -> Result<UnlockedVault, String> + Send + 'static) {
+        let events: Sender<SessionEvent> = self.events.clone();
+
+        thread::spawn(move || {
+            let _ = events.send(SessionEvent::VaultUnlocked(unlock()));
+        });
+    }
+
+    // Bounds every blocking SFTP call by the transfer timeout, commands and tunnels go back to no limit after
+    fn with_transfer_timeout<T>(&mut self, transfer: impl FnOnce(&mut Self) -> T) -> T {
+        if let Some(session) = &self.session {
+            session.set_timeout(self.transfer_timeout.as_millis() as u32);
+        }
+
+        let result: T = transfer(self);
+
+        if let Some(session) = &self.session {
+            session.set_timeout(0);
+        }
+
+        result
+    }
+
+    // A transfer error, or the timeout's own message if that is why it failed
+    fn transfer_error(&self, timed_out: bool, message: &str) -> String {
+        if timed_out {
+            format!("[SSH TIMEOUT] The transfer stalled for more than {} seconds", self.transfer_timeout.as_secs())
+        } else {
+            message.to_string()
+        }
+    }
+
+    // host:port, or [host]:port for IPv6 literals
+    fn get_remote_address(&self) -> String {
+        if self.remote_server.contains(':') {
+            format!("[{}]:{}", self.remote_server, self.port)
+        } else {
+            format!("{}:{}", self.remote_server, self.port)
+        }
+    }
+
+    // Returns the line to log
+    fn add_local_forward(&mut self, spec: &str) -> String {
+        if !self.have_logged_in || !self.session_still_valid {
+            return "[SSH FORWARD] Please log in before adding a forward".to_string();
+        }
+
+        match LocalForwardSpec::parse(spec).and_then(|spec| LocalForward::open(&spec)) {
+            Ok(forward) => {
+                let line = format!("[SSH FORWARD] Forwarding {} to {}:{}", forward.local_address, forward.remote_host, forward.remote_port);
+                self.local_forwards.push(forward);
+                line
+            }
+            Err(message) => message,
+        }
+    }
+
+    // Returns the line to log
+    fn add_remote_forward(&mut self, spec: &str) -> String {
+        if !self.have_logged_in || !self.session_still_valid {
+            return "[SSH FORWARD] Please log in before adding a forward".to_string();
+        }
+
+        let Some(session) = self.session.clone() else {
+            return "[SSH FORWARD] Please log in before adding a forward".to_string();
+        };
+
+        match RemoteForwardSpec::parse(spec).and_then(|spec| RemoteForward::open(&session, &spec)) {
+            Ok(forward) => {
+                let line = format!("[SSH FORWARD] The server is forwarding port {} to {}:{}", forward.remote_port, forward.local_host, forward.local_port);
+                self.remote_forwards.push(forward);
+                line
+            }
+            Err(message) => message,
+        }
+    }
+
+    // Stops the proxy if it's running, otherwise starts it on spec, falling back to --socks then the default port
+    fn toggle_socks(&mut self, spec: &str) -> String {
+        if let Some(mut proxy) = self.socks_proxy.take() {
+            proxy.close();
+            return format!("[SSH SOCKS] Stopped the proxy on {}", proxy.local_address);
+        }
+
+        if !self.have_logged_in || !self.session_still_valid {
+            return "[SSH SOCKS] Please log in before starting the proxy".to_string();
+        }
+
+        let spec: String = if spec.trim().is_empty() {
+            self.settings.socks_spec.clone().unwrap_or_default()
+        } else {
+            spec.to_string()
+        };
+
+        match SocksSpec::parse(&spec).and_then(|spec| SocksProxy::open(&spec)) {
+            Ok(proxy) => {
+                let line = format!("[SSH SOCKS] SOCKS5 proxy listening on {}", proxy.local_address);
+                self.socks_proxy = Some(proxy);
+                line
+            }
+            Err(message) => message,
+        }
+    }
+
+    // Stops the forwards using the given port, local for -L and remote for -R, or every forward if it's empty
+    fn stop_forwards(&mut self, port: &str) -> String {
+        let port: Option<u16> = if port.trim().is_empty() {
+            None
+        } else {
+            match parse_port(port.trim()) {
+                Ok(port) => Some(port),
+                Err(message) => return message,
+            }
+        };
+
+        let before: usize = self.local_forwards.len() + self.remote_forwards.len();
+
+        self.local_forwards.retain_mut(|forward| {
+            if port.is_none() || port == Some(forward.local_address.port()) {
+                forward.close();
+                false
+            } else {
+                true
+            }
+        });
+
+        // Dropping a remote forward's listener cancels it on the server
+        self.remote_forwards.retain_mut(|forward| {
+            if port.is_none() || port == Some(forward.remote_port) {
+                forward.close();
+                false
+            } else {
+                true
+            }
+        });
+
+        let after: usize = self.local_forwards.len() + self.remote_forwards.len();
+
+        format!("[SSH FORWARD] Stopped {} forward(s)", before - after)
+    }
+
+    // Lines describing the connection and every active tunnel, for the tunnel list
+    fn describe_tunnels(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+
+        if self.have_logged_in && self.session_still_valid {
+            lines.push(format!("Connected to {}@{}", self.username, self.get_remote_address()));
+
+            if let Some(directory) = &self.remote_directory {
+                lines.push(format!("Working directory {}", directory));
+            }
+        } else {
+            lines.push("Not connected".to_string());
+        }
+
+        if self.local_forwards.is_empty() && self.remote_forwards.is_empty() && self.socks_proxy.is_none() {
+            lines.push("No active tunnels".to_string());
+        }
+
+        if let Some(proxy) = &self.socks_proxy {
+            lines.push(proxy.describe());
+        }
+
+        lines.extend(self.local_forwards.iter().map(|forward| forward.describe()));
+        lines.extend(self.remote_forwards.iter().map(|forward| forward.describe()));
+
+        lines
+    }
+
+    // The spec each open tunnel was made from, so they can be opened again after reconnecting
+    fn forward_specs(&self) -> Vec<(ForwardAction, String)> {
+        let mut specs: Vec<(ForwardAction, String)> = Vec::new();
+
+        for forward in &self.local_forwards {
+            specs.push((ForwardAction::AddLocal, format!("{}:{}:{}", forward.local_address, bracket_ipv6(&forward.remote_host), forward.remote_port)));
+        }
+        for forward in &self.remote_forwards {
+            specs.push((ForwardAction::AddRemote, format!("{}:{}:{}", forward.remote_port, bracket_ipv6(&forward.local_host), forward.local_port)));
+        }
+        if let Some(proxy) = &self.socks_proxy {
+            specs.push((ForwardAction::ToggleSocks, proxy.local_address.to_string()));
+        }
+
+        specs
+    }
+
+    fn poll_forwards(&mut self) {
+        let mut log_lines: Vec<String> = Vec::new();
+
+        if self.have_logged_in && !self.restore_forwards.is_empty() {
+            for (action, spec) in std::mem::take(&mut self.restore_forwards) {
+                log_lines.push(match action {
+                    ForwardAction::AddLocal => self.add_local_forward(&spec),
+                    ForwardAction::AddRemote => self.add_remote_forward(&spec),
+                    ForwardAction::ToggleSocks => self.toggle_socks(&spec),
+                    ForwardAction::Stop => continue,
+                });
+            }
+        }
+
+        if self.socks_autostart && self.have_logged_in {
+            self.socks_autostart = false;
+
+            if self.socks_proxy.is_none() {
+                log_lines.push(self.toggle_socks(""));
+            }
+        }
+
+        if self.have_logged_in && let Some(session) = self.session.clone() {
+            for forward in &mut self.local_forwards {
+                log_lines.extend(forward.poll(&session, &mut self.channel_opener));
+            }
+            for forward in &mut self.remote_forwards {
+                log_lines.extend(forward.poll(&session));
+            }
+            if let Some(proxy) = &mut self.socks_proxy {
+                log_lines.extend(proxy.poll(&session, &mut self.channel_opener));
+            }
+            self.channel_opener.end_poll(&session);
+        }
+
+        for line in log_lines {
+            self.log(&line);
+        }
+    }
+
+    // Keepalives stop an idle connection being dropped by firewalls, and an error sending one means it already has been
+    fn poll_keepalive(&mut self) {
+        if !self.have_logged_in || self.settings.keepalive_interval == 0 || Instant::now() < self.next_keepalive {
+            return;
+        }
+
+        let Some(session) = self.session.clone() else {
+            return;
+        };
+
+        match session.keepalive_send() {
+            Ok(seconds_to_next) => {
+                self.next_keepalive = Instant::now() + Duration::from_secs(seconds_to_next.max(1) as u64);
+            }
+            Err(e) => self.connection_lost(e.message()),
+        }
+    }
+
+    // The connection carries on after the certificate expires, but logging in again won't work
+    fn poll_certificate(&mut self) {
+        if !self.have_logged_in || self.certificate_warned {
+            return;
+        }
+
+        let Some(seconds_left) = self.certificate.as_ref().and_then(|certificate| certificate.seconds_left()) else {
+            return;
+        };
+
+        if seconds_left <= CERTIFICATE_WARNING_SECONDS {
+            self.certificate_warned = true;
+            self.log(&format!("[SSH CERT] The certificate expires in {}. This session stays connected, but renew it before logging in or reconnecting", format_duration(seconds_left)));
+        }
+    }
+
+    // Called after a channel or SFTP error, to tell a failed request apart from a dead connection.
+    // Returns whether the connection is still there
+    fn check_transport(&mut self, reason: &str) -> bool {
+        let Some(session) = self.session.clone() else {
+            return false;
+        };
+
+        if self.transport_alive(&session) {
+            return true;
+        }
+
+        self.connection_lost(reason);
+        false
+    }
+
+    // A keepalive is the cheapest way to ask, but libssh2 doesn't send one while they're turned off,
+    // so opening and closing a channel stands in for it then
+    fn transport_alive(&mut self, session: &Session) -> bool {
+        if self.settings.keepalive_interval > 0 {
+            return session.keepalive_send().is_ok();
+        }
+
+        // Bounded, as a connection that has silently gone would otherwise never answer
+        session.set_timeout(self.connect_timeout.as_millis() as u32);
+        self.channel_opener.finish(session);
+        let alive: bool = session.channel_session()
+            .map(|mut channel| {
+                let _ = channel.close();
+            })
+            .is_ok();
+        session.set_timeout(0);
+
+        alive
+    }
+
+    // A tunnel's half done channel open is finished first, or the next blocking open would be given
+    // that channel instead of its own
+    fn session_for_new_channel(&mut self) -> Option<Session> {
+        let session: Session = self.session.clone()?;
+        self.channel_opener.finish(&session);
+
+        Some(session)
+    }
+
+    // Tears down everything that depended on the connection, remembering the tunnels to open again
+    fn connection_lost(&mut self, reason: &str) {
+        if !self.have_logged_in {
+            return;
+        }
+
+        self.log(&format!("[SSH ERROR] The connection to {} was lost: {}", self.get_remote_address(), reason));
+
+        self.restore_forwards = self.forward_specs();
+        self.close_connection();
+        self.session_still_valid = false;
+
+        if self.settings.auto_reconnect {
+            self.reconnect_attempts = 0;
+            self.next_reconnect = Some(Instant::now() + FIRST_RECONNECT_DELAY);
+            self.log(&format!("[SSH INFO] Reconnecting in {} seconds", FIRST_RECONNECT_DELAY.as_secs()));
+        } else {
+            self.log("[SSH HELP] ...Press Reconnect to log in again with the same details");
+        }
+    }
+
+    // Closes the tunnels, shell and session along with anything half way through logging in
+    fn close_connection(&mut self) {
+        for forward in &mut self.local_forwards {
+            forward.close();
+        }
+        for forward in &mut self.remote_forwards {
+            forward.close();
+        }
+        if let Some(proxy) = &mut self.socks_proxy {
+            proxy.close();
+        }
+        self.forget_shell();
+
+        self.local_forwards.clear();
+        self.remote_forwards.clear();
+        self.socks_proxy = None;
+        self.channel_opener.reset();
+        self.session = None;
+        self.tcp_stream = None;
+        self.pending_connection = None;
+        self.jump_tunnels.clear();
+        self.pending_jump = None;
+
+        if self.keyboard_interactive.take().is_some() {
+            let _ = self.events.send(SessionEvent::PromptsFinished);
+        }
+
+        self.have_logged_in = false;
+    }
+
+    // Logs out on purpose, so nothing tries to reconnect afterwards
+    fn disconnect(&mut self) {
+        let was_connected: bool = self.have_logged_in;
+
+        if !was_connected && self.pending_connection.is_none() && self.keyboard_interactive.is_none() && self.next_reconnect.is_none() {
+            self.log("[SSH INFO] Not connected to anything");
+            return;
+        }
+
+        // Tells the server we're going rather than just dropping the connection
+        if was_connected && let Some(session) = &self.session {
+            let _ = session.disconnect(Some(ssh2::DisconnectCode::ByApplication), "Logged out", None);
+        }
+
+        self.close_connection();
+        self.session_still_valid = true;
+        self.last_login = None;
+        self.restore_forwards.clear();
+        self.next_reconnect = None;
+        self.reconnect_attempts = 0;
+
+        if was_connected {
+            self.log(&format!("[SSH DISCONNECT] Logged out of {}@{}", self.username, self.get_remote_address()));
+        } else {
+            self.log(&format!("[SSH DISCONNECT] Stopped connecting to {}", self.get_remote_address()));
+        }
+    }
+
+    fn poll_reconnect(&mut self) {
+        if let Some(due) = self.next_reconnect
+            && Instant::now() >= due {
+            self.next_reconnect = None;
+            self.attempt_reconnect();
+        }
+    }
+
+    // Logs in with the last login's details, and with --reconnect schedules another go if it fails
+    fn attempt_reconnect(&mut self) {
+        let Some(request) = self.last_login.clone() else {
+            return;
+        };
+
+        self.reconnect_attempts += 1;
+        self.log(&format!("[SSH CONNECT] Reconnecting to {}...", self.get_remote_address()));
+
+        let ssh_result = self.make_ssh_handshake(request);
+        let failed: bool = ssh_result.is_err();
+        self.log_handshake_result(ssh_result);
+
+        if failed && self.settings.auto_reconnect {
+            if self.reconnect_attempts < MAX_RECONNECT_ATTEMPTS {
+                let delay: Duration = FIRST_RECONNECT_DELAY * 2u32.pow(self.reconnect_attempts);
+                self.next_reconnect = Some(Instant::now() + delay);
+                self.log(&format!("[SSH INFO] Trying again in {} seconds", delay.as_secs()));
+            } else {
+                self.log(&format!("[SSH WARN] Gave up reconnecting after {} attempts", self.reconnect_attempts));
+                self.log("[SSH HELP] ...Press Reconnect to try again");
+            }
+        }
+    }
+
+    // The command line's variables win over the config's, and its startup commands run after the config's
+    fn make_shell_profile(&mut self, host_config: &ResolvedHost) -> ShellProfile {
+        let mut profile = ShellProfile::new();
+
+        if let Some(setup) = self.settings.shell_setup.clone().or(host_config.shell_setup.clone()) {
+            profile.setup = setup;
+        }
+
+        for pair in self.settings.environment.iter().chain(&host_config.environment) {
+            match parse_environment_variable(pair) {
+                Some((name, value)) => {
+                    if !profile.environment.iter().any(|(known, _)| *known == name) {
+                        profile.environment.push((name, value));
+                    }
+                }
+                None => self.notices.push(format!("[SSH CONFIG] Ignoring the variable {}, it should look like NAME=value", pair)),
+            }
+        }
+
+        profile.startup_commands = host_config.startup_commands.iter()
+            .chain(&self.settings.startup_commands)
+            .cloned()
+            .collect();
+
+        profile
+    }
+
+    // An empty passphrase box falls back to the one given at startup, then to asking the askpass
+    // program, but only if the key actually needs one
+    fn find_passphrase(&mut self) {
+        if self.passphrase.is_empty() && let Some(passphrase) = &self.settings.passphrase {
+            self.passphrase = passphrase.clone();
+        }
+
+        let Some(askpass) = self.settings.askpass.clone().filter(|_| self.passphrase.is_empty()) else {
+            return;
+        };
+
+        let encrypted_key: Option<String> = match &self.private_key_data {
+            Some(data) => is_encrypted_key(data.expose()).then(|| "the pasted key".to_string()),
+            None if !self.private_key.is_empty() => Some(self.private_key.clone())
+                .filter(|path| fs::read_to_string(path).is_ok_and(|contents| is_encrypted_key(&contents))),
+            None => self.identity_files.iter()
+                .find(|path| fs::read_to_string(path).is_ok_and(|contents| is_encrypted_key(&contents)))
+                .cloned(),
+        };
+
+        let Some(key_name) = encrypted_key else {
+            return;
+        };
+
+        match read_secret_from_askpass(&askpass, &format!("Enter passphrase for {}: ", key_name)) {
+            Ok(passphrase) => self.passphrase = passphrase,
+            Err(message) => self.notices.push(message),
+        }
+    }
+
+    // Logs what the login's certificate allows and when it runs out, before it's offered to the server
+    fn find_certificate(&mut self) {
+        let keys: Vec<PathBuf> = if self.private_key.is_empty() {
+            self.identity_files.iter().map(PathBuf::from).filter(|path| path.exists()).collect()
+        } else {
+            vec![PathBuf::from(&self.private_key)]
+        };
+
+        let Some(path) = self.certificate_files.iter()
+            .cloned()
+            .chain(keys.iter().map(|key| certificate_path_for(key)))
+            .find(|path| path.exists()) else {
+            return;
+        };
+
+        let certificate: UserCertificate = match UserCertificate::load(&path) {
+            Ok(certificate) => certificate,
+            Err(message) => {
+                self.notices.push(message);
+                return;
+            }
+        };
+
+        self.notices.extend(certificate.describe());
+
+        if certificate.is_expired() {
+            self.notices.push("[SSH CERT] The certificate has expired, so only the key on its own will be tried".to_string());
+        } else if !certificate.allows(&self.username) {
+            self.notices.push(format!("[SSH CERT] The certificate isn't valid for {}, the server will likely refuse it", self.username));
+        }
+
+        self.certificate_warned = false;
+        self.certificate = Some(certificate);
+    }
+
+    // An empty result if there is no config, or it couldn't be read
+    fn load_host_config(&mut self, alias: &str) -> ResolvedHost {
+        let path: Option<PathBuf> = match &self.settings.config_path {
+            Some(path) => Some(path.clone()),
+            None => default_config_path().filter(|path| path.exists()),
+        };
+
+        match path.map(|path| SshConfig::load(&path)) {
+            Some(Ok(config)) => config.resolve(alias),
+            Some(Err(message)) => {
+                self.notices.push(message);
+                ResolvedHost::default()
+            }
+            None => ResolvedHost::default(),
+        }
+    }
+
+    // Tries every resolved address in turn, each bounded by the connect timeout
+    fn connect_tcp_stream(&self, target: &HostTarget) -> Result<TcpStream, HandshakeErrorCode> {
+        let addresses = target.resolve()
+            .map_err(HandshakeErrorCode::AddressFail)?;
+        let mut timed_out: bool = false;
+
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, self.connect_timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::TimedOut {
+                        timed_out = true;
+                    }
+                }
+            }
+        }
+
+        if timed_out {
+            Err(HandshakeErrorCode::TimeoutFail)
+        } else {
+            Err(HandshakeErrorCode::TcpFail)
+        }
+    }
+
+    // Each hop is logged into in turn with its own credentials, and the connection to the one after it is tunnelled through it
+    fn connect_through_jumps(&mut self, jumps: &[HostTarget], target: HostTarget, algorithms: AlgorithmPreferences, auth_method: AuthMethod) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        // Jump hosts can be config aliases too, and borrow the final username if they don't have one
+        let mut hops: Vec<JumpHop> = Vec::new();
+
+        for jump in jumps {
+            let jump_config: ResolvedHost = self.load_host_config(&jump.host);
+
+            hops.push(JumpHop {
+                target: HostTarget {
+                    username: jump.username.clone()
+                        .or(jump_config.user.clone())
+                        .or(Some(self.username.clone())),
+                    host: jump_config.hostname.clone().unwrap_or(jump.host.clone()),
+                    port: Some(jump.port.or(jump_config.port).unwrap_or(DEFAULT_SSH_PORT)),
+                },
+                algorithms: self.settings.algorithms.or(&jump_config.algorithms),
+                identity_files: jump_config.identity_files,
+            });
+        }
+
+        let pending = PendingJump {
+            hops,
+            hop_index: 0,
+            tunnels: Vec::new(),
+            target,
+            algorithms,
+            auth_method,
+        };
+
+        self.continue_jumps(pending, None)
+    }
+
+    // Logs into the hops from hop_index on, then the final host. Stops to wait if a hop asks for its own password
+    fn continue_jumps(&mut self, mut pending: PendingJump, mut next_stream: Option<TcpStream>) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        while let Some(hop) = pending.hops.get(pending.hop_index) {
+            let hop_stream: TcpStream = match next_stream.take() {
+                Some(stream) => stream,
+                None => self.connect_tcp_stream(&hop.target)?,
+            };
+
+            let hop_host: String = hop.target.host.clone();
+            let login: HopLogin = self.open_jump_session(hop_stream, hop)
+                .map_err(|reason| HandshakeErrorCode::JumpFail(hop_host.clone(), reason))?;
+
+            let hop_session: Session = match login {
+                HopLogin::LoggedIn(session) => session,
+                HopLogin::AwaitingPrompts(auth) => {
+                    self.notices.push(format!("[SSH INFO] The jump host {} wants its own password", hop_host));
+                    self.keyboard_interactive = Some(auth);
+                    self.prompt_sent = false;
+                    self.pending_jump = Some(pending);
+                    return Ok(HandshakeStatus::AwaitingPrompts);
+                }
+            };
+
+            next_stream = Some(self.tunnel_past_hop(&mut pending, hop_session)?);
+        }
+
+        // Replacing the old tunnels closes any previous jumped connection
+        self.jump_tunnels = std::mem::take(&mut pending.tunnels);
+
+        let tcp_stream_attempt: TcpStream = next_stream.ok_or(HandshakeErrorCode::TcpFail)?;
+        self.finish_handshake(tcp_stream_attempt, &pending.algorithms, pending.auth_method)
+    }
+
+    // Carries on with a jumped connection once its hop has answered its own prompts
+    fn resume_jump(&mut self, mut pending: PendingJump, auth: KeyboardInteractiveAuth) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        let hop_host: String = pending.hops.get(pending.hop_index)
+            .map(|hop| hop.target.host.clone())
+            .unwrap_or_default();
+
+        let (hop_session, _) = auth.finish()
+            .map_err(|reason| HandshakeErrorCode::JumpFail(hop_host.clone(), reason))?;
+
+        if !hop_session.authenticated() {
+            return Err(HandshakeErrorCode::JumpFail(hop_host, "its password was not accepted".to_string()));
+        }
+
+        hop_session.set_timeout(0);
+
+        let stream: TcpStream = self.tunnel_past_hop(&mut pending, hop_session)?;
+        self.continue_jumps(pending, Some(stream))
+    }
+
+    // Opens a tunnel through a logged in hop to the next hop, or the final host after the last one
+    fn tunnel_past_hop(&mut self, pending: &mut PendingJump, hop_session: Session) -> Result<TcpStream, HandshakeErrorCode> {
+        let hop_host: String = pending.hops[pending.hop_index].target.host.clone();
+
+        let next: &HostTarget = match pending.hops.get(pending.hop_index + 1) {
+            Some(next_hop) => &next_hop.target,
+            None => &pending.target,
+        };
+
+        let (stream, tunnel) = JumpTunnel::open(hop_session, &next.host, next.port.unwrap_or(DEFAULT_SSH_PORT))
+            .map_err(|reason| HandshakeErrorCode::JumpFail(hop_host.clone(), reason))?;
+
+        self.notices.push(format!("[SSH INFO] Jumped through {}", hop_host));
+        pending.tunnels.push(tunnel);
+        pending.hop_index += 1;
+
+        Ok(stream)
+    }
+
+    // Jump hosts can't stop to ask about an unknown host key, so one is refused. A hop only ever gets
+    // the agent and its own identity files, then asks for its own password, so nothing meant for the
+    // final host is offered to it
+    fn open_jump_session(&mut self, hop_stream: TcpStream, hop: &JumpHop) -> Result<HopLogin, String> {
+        let mut hop_session = Session::new()
+            .map_err(|_| "could not create a session".to_string())?;
+
+        let prompt_stream: TcpStream = hop_stream.try_clone()
+            .map_err(|_| "could not share its connection".to_string())?;
+
+        hop_session.set_tcp_stream(hop_stream);
+        hop_session.set_timeout(self.connect_timeout.as_millis() as u32);
+
+        hop.algorithms.apply(&hop_session)?;
+
+        hop_session.handshake()
+            .map_err(|e| if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_KEX_FAILURE) {
+                "it has no algorithms in common with the ones offered, check its KexAlgorithms, Ciphers and MACs".to_string()
+            } else {
+                format!("the handshake failed ({})", e.message())
+            })?;
+
+        let port: u16 = hop.target.port.unwrap_or(DEFAULT_SSH_PORT);
+
+        match check_host_key(&hop_session, &hop.target.host, port) {
+            Ok(HostKeyStatus::Trusted) => {/* Can Continue */}
+            Ok(HostKeyStatus::Unknown(fingerprint)) => {
+                return Err(format!("its host key {} isn't in known_hosts yet, log into it directly once to trust it", fingerprint));
+            }
+            Ok(HostKeyStatus::Changed(fingerprint)) => {
+                return Err(format!("its host key has CHANGED to {}, refusing to go through it", fingerprint));
+            }
+            Err(message) => {
+                return Err(message);
+            }
+        }
+
+        let username: String = hop.target.username.clone().unwrap_or_default();
+        let mut logged_in: bool = self.authenticate_with_agent(&hop_session, &username).is_ok();
+
+        // Encrypted keys for hops need to be in the agent, as the passphrase box belongs to the final host
+        for private_key in hop.identity_files.iter().map(Path::new).filter(|path| path.exists()) {
+            if logged_in {
+                break;
+            }
+
+            let certificate: PathBuf = certificate_path_for(private_key);
+
+            logged_in = (certificate.exists() && hop_session.userauth_pubkey_file(&username, Some(&certificate), private_key, None).is_ok())
+                || hop_session.userauth_pubkey_file(&username, None, private_key, None).is_ok();
+        }
+
+        if hop_session.authenticated() {
+            hop_session.set_timeout(0);
+            return Ok(HopLogin::LoggedIn(hop_session));
+        }
+
+        let methods: String = hop_session.auth_methods(&username)
+            .map(|m| m.to_string())
+            .unwrap_or_default();
+
+        if methods.contains("keyboard-interactive") {
+            Ok(HopLogin::AwaitingPrompts(KeyboardInteractiveAuth::start(hop_session, prompt_stream, username, None)))
+        } else if methods.contains("password") {
+            let prompt_text: String = format!("Password for {}@{}: ", username, hop.target.host);
+            Ok(HopLogin::AwaitingPrompts(KeyboardInteractiveAuth::start_password(hop_session, prompt_stream, username, prompt_text)))
+        } else {
+            Err(format!("could not authenticate as {}", username))
+        }
+    }
+
+    fn make_ssh_handshake(&mut self, request: LoginRequest) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        self.last_login = Some(request.clone());
+
+        let typed_target = HostTarget::parse(&request.hostname)
+            .map_err(HandshakeErrorCode::AddressFail)?;
+
+        // The hostname may be an alias from ~/.ssh/config
+        let host_config: ResolvedHost = self.load_host_config(&typed_target.host);
+
+        // Anything typed in wins over --port and the config, and a username field wins over user@
+        let target = HostTarget {
+            username: Some(request.username).filter(|u| !u.is_empty())
+                .or(typed_target.username.clone())
+                .or(host_config.user.clone()),
+            host: host_config.hostname.clone().unwrap_or(typed_target.host.clone()),
+            port: Some(typed_target.port
+                .or(self.settings.default_port)
+                .or(host_config.port)
+                .unwrap_or(DEFAULT_SSH_PORT)),
+        };
+
+        self.remote_server = target.host.clone();
+        self.port = target.port.unwrap_or(DEFAULT_SSH_PORT);
+        self.username = target.username.clone()
+            .ok_or(HandshakeErrorCode::MissingUsername)?;
+        self.password = request.password;
+        self.public_key = request.public_key;
+        self.private_key = request.private_key;
+        self.passphrase = request.passphrase;
+        self.private_key_data = request.private_key_data;
+        self.jump_hosts = request.jump_hosts;
+
+        // --connect-timeout wins over the config's ConnectTimeout
+        self.connect_timeout = self.settings.connect_timeout
+            .or(host_config.connect_timeout.map(Duration::from_secs))
+            .unwrap_or(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECONDS));
+
+        // Likewise --command-timeout and --transfer-timeout win over the config's own keywords
+        self.command_timeout = self.settings.command_timeout
+            .or(host_config.command_timeout.map(Duration::from_secs))
+            .filter(|timeout| !timeout.is_zero());
+        self.transfer_timeout = self.settings.transfer_timeout
+            .or(host_config.transfer_timeout.map(Duration::from_secs))
+            .unwrap_or(Duration::from_secs(DEFAULT_TRANSFER_TIMEOUT_SECONDS));
+
+        self.shell_profile = self.make_shell_profile(&host_config);
+
+        if target.host != typed_target.host || host_config.user.is_some() {
+            self.notices.push(format!("[SSH CONFIG] {} resolved to {}@{}", typed_target.host, self.username, self.get_remote_address()));
+        }
+
+        // Without a typed in key, the config's identity files are used instead of a missing
+        // password, or instead of the agent when IdentitiesOnly is set. Like ssh, the keys in
+        // ~/.ssh stand in when the config doesn't name any
+        let key_given: bool = !self.private_key.is_empty() || self.private_key_data.is_some();
+
+        self.identity_files = if key_given {
+            Vec::new()
+        } else if !host_config.identity_files.is_empty() {
+            host_config.identity_files.clone()
+        } else {
+            discover_identities().iter().map(|path| path.display().to_string()).collect()
+        };
+
+        let auth_method: AuthMethod = match request.auth_method {
+            AuthMethod::Password if self.password.is_empty() && !self.identity_files.is_empty() => AuthMethod::KeyFile,
+            AuthMethod::Agent if host_config.identities_only && !self.identity_files.is_empty() => {
+                self.notices.push("[SSH CONFIG] IdentitiesOnly is set, using the config's identity files instead of the agent".to_string());
+                AuthMethod::KeyFile
+            }
+            other => other,
+        };
+
+        self.certificate_files = self.settings.certificate_path.iter()
+            .cloned()
+            .chain(host_config.certificate_files.iter().map(PathBuf::from))
+            .collect();
+        self.certificate = None;
+
+        if auth_method == AuthMethod::KeyFile {
+            if !key_given && host_config.identity_files.is_empty() {
+                self.notices.push(format!("[SSH KEY] No key was given, trying the {} found in ~/.ssh", self.identity_files.len()));
+            }
+
+            self.find_certificate();
+            self.find_passphrase();
+        }
+
+        // Jump hosts typed in win over the config's ProxyJump
+        let jump_list: String = if self.jump_hosts.trim().is_empty() {
+            host_config.proxy_jump.clone().unwrap_or_default()
+        } else {
+            self.jump_hosts.clone()
+        };
+        let jumps: Vec<HostTarget> = parse_jump_list(&jump_list)
+            .map_err(HandshakeErrorCode::AddressFail)?;
+
+        // The config's and command line's algorithm lists replace libssh2's defaults
+        let algorithms: AlgorithmPreferences = self.settings.algorithms.or(&host_config.algorithms);
+
+        // Create a TcpStream to the host and port, without the username, either directly or
+        // tunnelled through the jump hosts
+        if !jumps.is_empty() {
+            return self.connect_through_jumps(&jumps, target, algorithms, auth_method);
+        }
+
+        let tcp_stream_attempt: TcpStream = self.connect_tcp_stream(&target)?;
+        self.finish_handshake(tcp_stream_attempt, &algorithms, auth_method)
+    }
+
+    // Handshakes and logs in over a stream to the final host, whether direct or through the jump hosts
+    fn finish_handshake(&mut self, tcp_stream_attempt: TcpStream, algorithms: &AlgorithmPreferences, auth_method: AuthMethod) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        // Create a session value to hold the session
+        let mut session_attempt = Session::new()
+            .map_err(|_| HandshakeErrorCode::SessionFail)?;
+
+        // Link the two
+        session_attempt.set_tcp_stream(tcp_stream_attempt.try_clone()
+            .map_err(|_| HandshakeErrorCode::TcpFail)?);
+
+        // Bound the handshake and authentication by the same timeout as the connection
+        session_attempt.set_timeout(self.connect_timeout.as_millis() as u32);
+
+        let warnings: Vec<String> = algorithms.apply(&session_attempt)
+            .map_err(HandshakeErrorCode::AlgorithmFail)?;
+        self.notices.extend(warnings);
+
+        // Attempt to handshake
+        match session_attempt.handshake() {
+            Ok(()) => {
+                self.notices.extend(describe_negotiated(&session_attempt));
+            }
+            Err(e) => {
+                if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT) {
+                    return Err(HandshakeErrorCode::TimeoutFail);
+                }
+                if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_KEX_FAILURE) {
+                    return Err(HandshakeErrorCode::NegotiationFail(algorithms.describe_offered(&session_attempt)));
+                }
+                // The user may have entered an invalid hostname, so don't necessarily destroy the session validity yet
+                return Err(HandshakeErrorCode::HandshakeFail);
+            }
+        }
+
+        // Check the server is who it claims to be before sending it any credentials
+        match check_host_key(&session_attempt, &self.remote_server, self.port) {
+            Ok(HostKeyStatus::Trusted) => {/* Can Continue */}
+            Ok(HostKeyStatus::Unknown(fingerprint)) => {
+                // Authentication waits until the user trusts or rejects the host
+                self.pending_connection = Some((session_attempt, tcp_stream_attempt, auth_method));
+                return Ok(HandshakeStatus::AwaitingHostKey(fingerprint));
+            }
+            Ok(HostKeyStatus::Changed(fingerprint)) => {
+                return Err(HandshakeErrorCode::HostKeyMismatch(fingerprint));
+            }
+            Err(message) => {
+                return Err(HandshakeErrorCode::HostKeyCheckFail(message));
+            }
+        }
+
+        self.authenticate_session(session_attempt, tcp_stream_attempt, auth_method)
+    }
+
+    // Called once the user has trusted or rejected an unknown host key, returns None if nothing was waiting
+    fn resolve_pending_host_key(&mut self, trust: bool) -> Option<Result<HandshakeStatus, HandshakeErrorCode>> {
+        let (session_attempt, tcp_stream_attempt, auth_method) = self.pending_connection.take()?;
+
+        if !trust {
+            return Some(Err(HandshakeErrorCode::HostKeyRejected));
+        }
+
+        if let Err(message) = add_host_key(&session_attempt, &self.remote_server, self.port) {
+            return Some(Err(HandshakeErrorCode::HostKeyCheckFail(message)));
+        }
+
+        Some(self.authenticate_session(session_attempt, tcp_stream_attempt, auth_method))
+    }
+
+    fn authenticate_session(&mut self, session_attempt: Session, tcp_stream_attempt: TcpStream, auth_method: AuthMethod) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        let auth_result: Result<(), HandshakeErrorCode> = match auth_method {
+            AuthMethod::Agent => {
+                let username: String = self.username.clone();
+
+                // Without a reachable agent, fall back to whatever else the form was given
+                match self.authenticate_with_agent(&session_attempt, &username) {
+                    Err(HandshakeErrorCode::AgentUnreachable) if self.has_key() => {
+                        self.notices.push("[SSH INFO] No ssh-agent was reachable, using the key file instead".to_string());
+                        // The same key selection a key file login makes, so pasted and config keys count too
+                        self.find_certificate();
+                        self.find_passphrase();
+                        self.authenticate_with_key_file(&session_attempt, &username)
+                    }
+                    Err(HandshakeErrorCode::AgentUnreachable) if !self.password.is_empty() => {
+                        self.notices.push("[SSH INFO] No ssh-agent was reachable, using the password instead".to_string());
+                        self.authenticate_with_password(&session_attempt, &self.username)
+                    }
+                    other => other,
+                }
+            }
+            AuthMethod::KeyFile => {
+                let username: String = self.username.clone();
+                let key_result = self.authenticate_with_key_file(&session_attempt, &username);
+
+                // Keys nobody typed in were only a guess, so like ssh the server's own prompts get a go after them
+                let guessed: bool = self.private_key.is_empty() && self.private_key_data.is_none();
+
+                if key_result.is_err() && guessed && !session_attempt.authenticated()
+                    && session_attempt.auth_methods(&self.username).is_ok_and(|methods| methods.contains("keyboard-interactive")) {
+                    self.keyboard_interactive = Some(KeyboardInteractiveAuth::start(session_attempt, tcp_stream_attempt, self.username.clone(), None));
+                    self.prompt_sent = false;
+                    return Ok(HandshakeStatus::AwaitingPrompts);
+                }
+
+                key_result
+            }
+            AuthMethod::Password => {
+                // Asking for the methods also shows whether the server let us in without any
+                let methods: String = session_attempt.auth_methods(&self.username)
+                    .map(|m| m.to_string())
+                    .unwrap_or_default();
+                let try_password: bool = !self.password.is_empty() && (methods.is_empty() || methods.contains("password"));
+
+                let password_result = if try_password {
+                    self.authenticate_with_password(&session_attempt, &self.username)
+                } else {
+                    Err(HandshakeErrorCode::LoginAuthFail)
+                };
+
+                // Password plus one-time code setups need keyboard-interactive, either instead of
+                // the password or after it partially succeeded
+                if !session_attempt.authenticated() && methods.contains("keyboard-interactive") {
+                    let known_password = if try_password {
+                        None
+                    } else {
+                        Some(self.password.clone()).filter(|p| !p.is_empty())
+                    };
+
+                    self.keyboard_interactive = Some(KeyboardInteractiveAuth::start(session_attempt, tcp_stream_attempt, self.username.clone(), known_password));
+                    self.prompt_sent = false;
+                    return Ok(HandshakeStatus::AwaitingPrompts);
+                }
+
+                password_result
+            }
+        };
+        auth_result?;
+
+        self.complete_login(session_attempt, tcp_stream_attempt)
+    }
+
+    fn complete_login(&mut self, session_attempt: Session, tcp_stream_attempt: TcpStream) -> Result<HandshakeStatus, HandshakeErrorCode> {
+        if !session_attempt.authenticated() {
+            return Err(HandshakeErrorCode::SessionAuthFail);
+        }
+
+        // Commands and transfers shouldn't be cut off by the connection timeout
+        session_attempt.set_timeout(0);
+
+        if self.settings.keepalive_interval > 0 {
+            session_attempt.set_keepalive(true, self.settings.keepalive_interval);
+            self.next_keepalive = Instant::now() + Duration::from_secs(self.settings.keepalive_interval as u64);
+        }
+
+        self.session = Some(session_attempt);
+        self.tcp_stream = Some(tcp_stream_attempt);
+        self.have_logged_in = true;
+        self.session_still_valid = true;
+        self.reconnect_attempts = 0;
+        // Any shell from a previous connection went with it
+        self.forget_shell();
+        Ok(HandshakeStatus::LoggedIn)
+    }
+
+    // Sends the server's keyboard-interactive prompts to the UI one at a time, and finishes the login once it's done
+    fn poll_keyboard_interactive(&mut self) {
+        let Some(auth) = self.keyboard_interactive.as_mut() else {
+            return;
+        };
+
+        if !self.prompt_sent && let Some((instructions, prompt)) = auth.next_prompt() {
+            if let Some(text) = instructions {
+                self.log(&format!("[SSH PROMPT] {}", text));
+            }
+            self.log(&format!("[SSH PROMPT] {}", prompt.text));
+
+            let _ = self.events.send(SessionEvent::Prompt(prompt));
+            self.prompt_sent = true;
+            return;
+        }
+
+        if auth.is_finished() {
+            let _ = self.events.send(SessionEvent::PromptsFinished);
+            self.prompt_sent = false;
+
+            if let Some(auth) = self.keyboard_interactive.take() {
+                let ssh_result = match self.pending_jump.take() {
+                    Some(pending) => self.resume_jump(pending, auth),
+                    None => match auth.finish() {
+                        Ok((session_attempt, tcp_stream_attempt)) => self.complete_login(session_attempt, tcp_stream_attempt),
+                        Err(message) => Err(HandshakeErrorCode::KeyboardInteractiveFail(message)),
+                    },
+                };
+
+                self.log_handshake_result(ssh_result);
+            }
+        }
+    }
+
+    // A typed in or pasted key, or identity files from the config or ~/.ssh
+    fn has_key(&self) -> bool {
+        !self.private_key.is_empty() || self.private_key_data.is_some() || !self.identity_files.is_empty()
+    }
+
+    fn authenticate_with_key_file(&mut self, session_attempt: &Session, username: &str) -> Result<(), HandshakeErrorCode> {
+        // Copied so the attempts can be added to the notices as they're made
+        let passphrase_secret: Secret = self.passphrase.clone();
+        let passphrase = if passphrase_secret.is_empty() {
+            None
+        } else {
+            Some(passphrase_secret.expose())
+        };
+
+        if let Some(private_key) = &self.private_key_data {
+            return self.authenticate_with_pasted_key(session_attempt, username, private_key.expose(), passphrase);
+        }
+
+        if !self.private_key.is_empty() {
+            let private_key: PathBuf = PathBuf::from(&self.private_key);
+            let public_key: Option<PathBuf> = if self.public_key.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(&self.public_key))
+            };
+
+            if self.authenticate_with_certificates(session_attempt, username, &private_key, passphrase) {
+                return Ok(());
+            }
+
+            return session_attempt.userauth_pubkey_file(username, public_key.as_deref(), &private_key, passphrase)
+                .map_err(|_| HandshakeErrorCode::LoginAuthFail);
+        }
+
+        // Identity files from the config, skipping any that don't exist like ssh does
+        let identity_files: Vec<String> = self.identity_files.clone();
+
+        for private_key in identity_files.iter().map(Path::new).filter(|path| path.exists()) {
+            let public_key_path: PathBuf = PathBuf::from(format!("{}.pub", private_key.display()));
+            let public_key: Option<&Path> = Some(public_key_path.as_path()).filter(|path| path.exists());
+
+            self.notices.push(format!("[SSH KEY] Trying the identity file {}", private_key.display()));
+
+            if self.authenticate_with_certificates(session_attempt, username, private_key, passphrase)
+                || session_attempt.userauth_pubkey_file(username, public_key, private_key, passphrase).is_ok() {
+                return Ok(());
+            }
+        }
+
+        Err(HandshakeErrorCode::LoginAuthFail)
+    }
+
+    // The public key box can hold a pasted public key too, or a path to one. Without either, libssh2
+    // works the public key out from the private key
+    fn authenticate_with_pasted_key(&self, session_attempt: &Session, username: &str, private_key: &str, passphrase: Option<&str>) -> Result<(), HandshakeErrorCode> {
+        let public_key: Option<String> = if looks_like_public_key(&self.public_key) {
+            Some(self.public_key.clone())
+        } else if self.public_key.is_empty() {
+            None
+        } else {
+            fs::read_to_string(&self.public_key).ok()
+        };
+
+        for certificate in self.certificate_files.iter().filter_map(|path| fs::read_to_string(path).ok()) {
+            if userauth_pubkey_memory(session_attempt, username, Some(&certificate), private_key, passphrase).is_ok() {
+                return Ok(());
+            }
+        }
+
+        userauth_pubkey_memory(session_attempt, username, public_key.as_deref(), private_key, passphrase)
+            .map_err(|_| HandshakeErrorCode::LoginAuthFail)
+    }
+
+    // libssh2 offers whatever public key file it's given, so a certificate goes in its place
+    fn authenticate_with_certificates(&mut self, session_attempt: &Session, username: &str, private_key: &Path, passphrase: Option<&str>) -> bool {
+        let candidates: Vec<PathBuf> = self.certificate_files.iter()
+            .cloned()
+            .chain(std::iter::once(certificate_path_for(private_key)))
+            .filter(|path| path.exists())
+            .collect();
+
+        for certificate in candidates {
+            // Expired ones would only use up one of the server's MaxAuthTries
+            if UserCertificate::load(&certificate).is_ok_and(|c| c.is_expired()) {
+                continue;
+            }
+
+            self.notices.push(format!("[SSH CERT] Trying the certificate {}", certificate.display()));
+
+            if session_attempt.userauth_pubkey_file(username, Some(&certificate), private_key, passphrase).is_ok() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn authenticate_with_password(&self, session_attempt: &Session, username: &str) -> Result<(), HandshakeErrorCode> {
+        session_attempt.userauth_password(username, self.password.expose())
+            .map_err(|_| HandshakeErrorCode::LoginAuthFail)
+    }
+
+    // Offers every identity the agent holds until one is accepted
+    fn authenticate_with_agent(&mut self, session_attempt: &Session, username: &str) -> Result<(), HandshakeErrorCode> {
+        let mut agent = session_attempt.agent()
+            .map_err(|_| HandshakeErrorCode::AgentUnreachable)?;
+
+        agent.connect()
+            .map_err(|_| HandshakeErrorCode::AgentUnreachable)?;
+
+        let identities = agent.list_identities()
+            .and_then(|_| agent.identities())
+            .map_err(|_| HandshakeErrorCode::AgentUnreachable)?;
+
+        for identity in &identities {
+            self.notices.push(format!("[SSH INFO] Trying the agent's key {}", identity.comment()));
+
+            if agent.userauth(username, identity).is_ok() && session_attempt.authenticated() {
+                let _ = agent.disconnect();
+                return Ok(());
+            }
+        }
+
+        let _ = agent.disconnect();
+        Err(HandshakeErrorCode::AgentAuthFail(identities.len()))
+    }
+
+    // The shell's working directory, which transfers are relative to
+    fn remote_directory(&mut self) -> Result<String, String> {
+        if !self.have_logged_in || !self.session_still_valid {
+            return Err("[SSH WARNING] Please log in before transferring files".to_string());
+        }
+
+        // Known from the last command, or from starting the shell if nothing has run yet
+        if self.remote_directory.is_none() {
+            self.open_shell()?;
+        }
+
+        self.remote_directory
+            .clone()
+            .ok_or("[SSH ERROR] Could not find the remote working directory".to_string())
+    }
+
+    // Returns Filename with directory if applicable, or error message
+    fn download_file(&mut self, filename: &str, directory: &str) -> Result<String, String> {
+        let current_session = self.session_for_new_channel()
+            .unwrap();
+
+        // Attempt to create a SFTP session
+        let Ok(sftp_session) = current_session.sftp() else {
+            self.check_transport("could not open an SFTP session");
+            return Err("[SSH ERROR] Error establishing an SFTP session".to_string());
+        };
+
+        // Directory is aquired through 'pwd' which has a \n at the end
+        let target_file_name: String = format!("{}/{}", directory.trim_matches('\n'), filename);
+
+        // Debug info for terminal
+        println!("Downloading {}", target_file_name);
+
+        // Open the file
+        let mut target_file = sftp_session.open(Path::new(&target_file_name))
+            .map_err(|e| self.transfer_error(is_timeout(&e), "[SSH WARN] Problem creating file link"))?;
+
+        // Read contents into vector of strings
+        let mut downloaded_content = Vec::<u8>::new();
+
+        target_file.read_to_end(&mut downloaded_content)
+            .map_err(|e| self.transfer_error(e.kind() == std::io::ErrorKind::TimedOut, "[SSH WARN] There was a problem trying to download the file contents"))?;
+
+        // Save the contents into the desired file
+        std::fs::write(filename, downloaded_content)
+            .map_err(|_| "[SSH WARN] Problem creating a local save file to store the data in")?;
+
+        Ok(filename.to_string())
+    }
+
+    fn upload_file(&mut self, filename: &str, directory: &str) -> Result<String, String> {
+        let current_session = self.session_for_new_channel()
+            .unwrap();
+
+        let Ok(sftp_session) = current_session.sftp() else {
+            self.check_transport("could not open an SFTP session");
+            return Err("[SSH ERROR] Error establishing an SFTP session".to_string());
+        };
+
+        let target_destination = format!("{}/{}", directory.trim_matches('\n'), filename);
+
+        let mut local_file = File::open(filename)
+            .map_err(|_| "[SSH WARN] There was a problem finding the file to upload".to_string())?;
+        let mut file_contents = Vec::new();
+
+        local_file.read_to_end(&mut file_contents)
+            .map_err(|_| "[SSH WARN] There was a problem reading the file to upload".to_string())?;
+
+        // Now create the file in the remote server
+        let mut target_file = sftp_session.create(Path::new(&target_destination))
+            .map_err(|e| self.transfer_error(is_timeout(&e), "[SSH WARN] Could not create file link in destination folder"))?;
+
+        target_file.write_all(&file_contents)
+            .map_err(|e| self.transfer_error(e.kind() == std::io::ErrorKind::TimedOut, "[SSH WARN] Could not write data to target file"))?;
+
+        Ok(target_destination)
+    }
+
+    // Creates the directories first, and only uploads the files if they were all made
+    fn upload_directory(&mut self, directories: &[String], files: &[String]) {
+        let mut directory_success: bool = true;
+
+        for curr_dir in directories {
+            // Log in terminal as debug info
+            println!("Making directory {}", curr_dir);
+
+            if !self.have_logged_in || !self.session_still_valid {
+                continue;
+            }
+
+            // -p so a directory that is already there isn't a failure
+            match self.execute_command(&format!("mkdir -p {}", shell_quote(curr_dir))) {
+                Ok(result) if result.succeeded() => {}
+                Ok(result) => {
+                    self.log(&format!("[SSH WARN] Could not make {}: {}", curr_dir, result.stdout));
+                    directory_success = false;
+                }
+                Err(e) => {
+                    self.log(&e);
+                    directory_success = false;
+                }
+            }
+        }
+
+        for curr_file in files {
+            // Log in terminal as debug info
+            println!("Attempting to upload {}", curr_file);
+
+            if self.have_logged_in && self.session_still_valid && directory_success {
+                let result = self.remote_directory()
+                    .and_then(|directory| self.upload_file(curr_file, &directory));
+
+                match result {
+                    Ok(filepath) => self.log(&format!("Uploaded file to {}", filepath)),
+                    Err(e) => self.log(&e),
+                }
+            }
+        }
+    }
+
+    // Starts the command in the shell, poll_command then logs its output as it arrives
+    fn run_command(&mut self, command: &str) {
+        if !self.have_logged_in || !self.session_still_valid {
+            self.log("[SSH WARNING] Please log in before running commands");
+            return;
+        }
+
+        if self.command_running() {
+            self.log("[SSH INFO] The last command is still running, please wait for it to finish or cancel it");
+            return;
+        }
+
+        if command.trim().is_empty() {
+            return;
+        }
+
+        println!("Executing command: {}", command);
+
+        let result = self.open_shell()
+            .and_then(|shell| shell.start(command));
+
+        if let Err(e) = result {
+            self.shell_failed(&e);
+            self.log(&format!("Execution Error: {}", &e));
+        }
+    }
+
+    // Interrupts the running command, and if it ignores that poll_command closes its shell
+    fn cancel_command(&mut self) {
+        let Some(shell) = self.shell.as_mut().filter(|shell| shell.is_running()) else {
+            self.log("[SSH INFO] There is no command running to cancel");
+            return;
+        };
+
+        match shell.interrupt() {
+            Ok(()) => {
+                self.interrupted_at = Some(Instant::now());
+                self.log("[SSH INFO] Interrupted the running command");
+            }
+            Err(e) => {
+                self.shell_failed(&e);
+                self.log(&e);
+            }
+        }
+    }
+
+    fn command_running(&self) -> bool {
+        self.shell.as_ref().is_some_and(|shell| shell.is_running())
+    }
+
+    // Logs whatever the running command has printed since the last time round the loop
+    fn poll_command(&mut self) {
+        if !self.command_running() {
+            return;
+        }
+
+        let Some(shell) = self.shell.as_mut() else {
+            return;
+        };
+
+        if let Some(limit) = self.command_timeout
+            && self.interrupted_at.is_none()
+            && shell.running_for() > limit {
+            let interrupted = shell.interrupt();
+            self.interrupted_at = Some(Instant::now());
+
+            match interrupted {
+                Ok(()) => self.log(&format!("[SSH TIMEOUT] The command ran for longer than {} seconds, interrupting it", limit.as_secs())),
+                Err(e) => {
+                    self.shell_failed(&e);
+                    self.log(&e);
+                }
+            }
+            return;
+        }
+
+        let Some(shell) = self.shell.as_mut() else {
+            return;
+        };
+
+        match shell.poll() {
+            Ok(output) => {
+                // Filter out excessive results
+                for line in output.stdout.iter().chain(output.stderr.iter()) {
+                    match line.char_indices().nth(MAX_LOGGER_LINE_LENGTH) {
+                        Some((cut, _)) => self.log(&line[..cut]),
+                        None => self.log(line),
+                    }
+                }
+
+                if let Some(result) = output.result {
+                    self.interrupted_at = None;
+                    self.sync_working_directory();
+                    self.log(&result.describe_exit());
+                } else if self.interrupted_at.is_some_and(|at| at.elapsed() > INTERRUPT_GRACE_PERIOD) {
+                    // Closing the channel hangs up on the command, the next one gets a fresh shell
+                    self.forget_shell();
+                    self.log("[SSH WARN] The command ignored the interrupt, so its shell was closed. Directory changes and variables from it are lost");
+                }
+            }
+            Err(e) => {
+                self.shell_failed(&e);
+                self.log(&format!("Execution Error: {}", &e));
+            }
+        }
+    }
+
+    // The session's shell, which is started on first use and kept open
+    fn open_shell(&mut self) -> Result<&mut ShellChannel, String> {
+        if self.shell.as_ref().is_none_or(|shell| shell.is_closed()) {
+            let current_session = self.session_for_new_channel()
+                .ok_or("[SSH WARNING] Please log in before running commands".to_string())?;
+
+            let mut shell = ShellChannel::open(&current_session, &self.shell_profile)
+                .inspect_err(|e| {
+                    self.check_transport(e);
+                })?;
+
+            let timeout: Duration = self.command_timeout.unwrap_or(HELPER_COMMAND_TIMEOUT);
+
+            // A failing startup command is only a warning, but one that won't finish leaves the shell unusable
+            for command in self.shell_profile.startup_commands.clone() {
+                match shell.run(&command, timeout) {
+                    Ok(result) if result.succeeded() => {}
+                    Ok(result) => {
+                        for line in result.stdout.lines() {
+                            self.log(line);
+                        }
+                        self.log(&format!("[SSH WARN] The startup command '{}' failed. {}", command, result.describe_exit()));
+                    }
+                    Err(e) => {
+                        shell.close();
+                        self.check_transport(&e);
+                        return Err(format!("{}. Check the startup commands", e));
+                    }
+                }
+            }
+
+            self.shell = Some(shell);
+            self.sync_working_directory();
+        }
+
+        self.shell
+            .as_mut()
+            .ok_or("[SSH ERROR] Could not open a shell channel".to_string())
+    }
+
+    // The shell is gone, or stuck part way through some output. If the connection is still there
+    // only the shell went, like after typing exit, so a new one is started in its place
+    fn shell_failed(&mut self, reason: &str) {
+        self.forget_shell();
+
+        if self.check_transport(reason) {
+            self.log("[SSH INFO] The shell closed, starting a new one");
+
+            if let Err(e) = self.open_shell() {
+                self.log(&e);
+            }
+        }
+    }
+
+    // Closes the shell, and with it goes the working directory it had
+    fn forget_shell(&mut self) {
+        if let Some(mut shell) = self.shell.take() {
+            shell.close();
+        }
+        self.interrupted_at = None;
+        self.shell_directory = None;
+
+        if self.remote_directory.take().is_some() {
+            let _ = self.events.send(SessionEvent::WorkingDirectory(None));
+        }
+    }
+
+    // Picks up a change of directory from the last command, like cd or pushd
+    fn sync_working_directory(&mut self) {
+        let Some(directory) = self.shell.as_ref().and_then(|shell| shell.working_directory()).map(|d| d.to_string()) else {
+            return;
+        };
+
+        if self.shell_directory.as_ref() == Some(&directory) {
+            return;
+        }
+
+        // $PWD can go through symlinks, SFTP's realpath gives where it really is
+        let resolved: String = self.session_for_new_channel()
+            .and_then(|session| session.sftp().ok())
+            .and_then(|sftp| sftp.realpath(Path::new(&directory)).ok())
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or(directory.clone());
+
+        self.shell_directory = Some(directory);
+        self.remote_directory = Some(resolved.clone());
+        let _ = self.events.send(SessionEvent::WorkingDirectory(Some(resolved)));
+    }
+
+    // Runs the command in the shell and waits for its output, for things like pwd that need the answer straight away
+    fn execute_command(&mut self, new_command: &str) -> Result<CommandResult, String> {
+        if self.command_running() {
+            return Err("[SSH INFO] The last command is still running, please wait for it to finish".to_string());
+        }
+
+        let timeout: Duration = self.command_timeout.unwrap_or(HELPER_COMMAND_TIMEOUT);
+
+        let result = self.open_shell()
+            .and_then(|shell| shell.run(new_command, timeout));
+
+        // A command still running after an error was interrupted for taking too long, and poll_command
+        // finishes it off. Otherwise the shell itself failed
+        result.inspect_err(|e| {
+            if self.command_running() {
+                self.interrupted_at = Some(Instant::now());
+            } else {
+                self.shell_failed(e);
+            }
+        })
+    }
+
+    fn log_handshake_result(&mut self, ssh_result: Result<HandshakeStatus, HandshakeErrorCode>) {
+        for notice in std::mem::take(&mut self.notices) {
+            self.log(&notice);
+        }
+
+        match ssh_result {
+            Ok(HandshakeStatus::LoggedIn) => {
+                self.log(&format!("[SSH INFO] Successful SSH into {}", self.get_remote_address()));
+            }
+            Ok(HandshakeStatus::AwaitingPrompts) => {
+                self.log("[SSH INFO] The server wants keyboard-interactive authentication");
+                self.log("[SSH HELP] ...Answer each of its prompts in the prompt box and press enter");
+            }
+            Ok(HandshakeStatus::AwaitingHostKey(fingerprint)) => {
+                self.log(&format!("[SSH HOSTKEY] The authenticity of {} can't be established", self.get_remote_address()));
+                self.log(&format!("[SSH HOSTKEY] Key fingerprint is {}", fingerprint));
+                self.log("[SSH HELP] ...Press Trust Host to save it to known_hosts and continue, or Reject Host");
+            }
+            Err(err_code) => {
+                match err_code {
+                    HandshakeErrorCode::AddressFail(message) => {
+                        self.log(&message);
+                        self.log("[SSH HELP] ...Use host, host:port, [IPv6]:port or user@host:port");
+                    }
+                    HandshakeErrorCode::MissingUsername => {
+                        self.log("[SSH CONNECT] There is a missing piece of info before attempting to log in.");
+                        self.log("[SSH HELP] ...No username was given, and ~/.ssh/config doesn't set one for this host");
+                    }
+                    HandshakeErrorCode::TimeoutFail => {
+                        self.log(&format!("[SSH TIMEOUT] Timed out after {} seconds trying to reach the host", self.connect_timeout.as_secs()));
+                        self.log("[SSH HELP] ...Is the host up, and is the port correct?");
+                    }
+                    HandshakeErrorCode::AlgorithmFail(message) => {
+                        self.log(&message);
+                        self.log("[SSH HELP] ...Check --kex-algorithms, --host-key-algorithms, --ciphers, --macs and the config's lists");
+                    }
+                    HandshakeErrorCode::NegotiationFail(offered) => {
+                        self.log("[SSH WARN] The server didn't agree to any of the algorithms offered to it");
+                        for line in offered {
+                            self.log(&line);
+                        }
+                        self.log("[SSH HELP] ...Add what it needs with something like --kex-algorithms +NAME, or KexAlgorithms, Ciphers and MACs in ~/.ssh/config");
+                    }
+                    HandshakeErrorCode::HostKeyCheckFail(message) => {
+                        self.log(&message);
+                    }
+                    HandshakeErrorCode::HostKeyMismatch(fingerprint) => {
+                        self.log(&format!("[SSH ERROR] The host key for {} has CHANGED, refusing to connect", self.get_remote_address()));
+                        self.log(&format!("[SSH ERROR] The server presented {}", fingerprint));
+                        self.log("[SSH HELP] ...Someone could be intercepting the connection. If the key change is expected, remove the old entry from known_hosts");
+                    }
+                    HandshakeErrorCode::HostKeyRejected => {
+                        self.log(&format!("[SSH INFO] Host key for {} rejected, not logging in", self.get_remote_address()));
+                    }
+                    HandshakeErrorCode::JumpFail(host, reason) => {
+                        self.log(&format!("[SSH WARN] Could not go through the jump host {}: {}", host, reason));
+                    }
+                    HandshakeErrorCode::AgentUnreachable => {
+                        self.log("[SSH WARN] Could not reach an ssh-agent");
+                        self.log("[SSH HELP] ...Is the agent running and SSH_AUTH_SOCK set? Otherwise fill in a key or password");
+                    }
+                    HandshakeErrorCode::AgentAuthFail(count) => {
+                        self.log(&format!("[SSH WARN] None of the {} identities in the ssh-agent were accepted", count));
+                    }
+                    HandshakeErrorCode::KeyboardInteractiveFail(message) => {
+                        self.log(&format!("[SSH WARN] Keyboard-interactive authentication failed: {}", message));
+                        self.log("[SSH HELP] ...Was the one-time code still valid?");
+                    }
+                    HandshakeErrorCode::TcpFail => {
+                        self.log("[SSH ERROR] Failed to establish a TCP Connection");
+                    }
+                    HandshakeErrorCode::SessionFail => {
+                        self.log("[SSH ERROR] Failed to establish a new session");
+                    }
+                    HandshakeErrorCode::HandshakeFail => {
+                        self.log("[SSH WARN] Failed to create a link between a TCP Connection and a Session");
+                        self.log("[SSH HELP] ...Did you perhaps misspell the hostname?");
+                    }
+                    HandshakeErrorCode::LoginAuthFail => {
+                        self.log("[SSH WARN] Failed to authenticate a login");
+                        self.log("[SSH HELP] ...Did you type your username and password correctly?");
+                    }
+                    HandshakeErrorCode::SessionAuthFail => {
+                        self.log("[SSH ERROR] Failed to authenticate a Session");
+                    }
+                }
+            }
+        }
+    }
+}
+
+// IPv6 literals need brackets to go back into a forward spec
+fn bracket_ipv6(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+// Names are letters, digits and underscores, not starting with a digit
+fn parse_environment_variable(pair: &str) -> Option<(String, String)> {
+    let (name, value) = pair.split_once('=')?;
+
+    let valid: bool = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    valid.then(|| (name.to_string(), value.to_string()))
+}
+
+#[cfg(unix)]
+fn userauth_pubkey_memory(session: &Session, username: &str, public_key: Option<&str>, private_key: &str, passphrase: Option<&str>) -> Result<(), ssh2::Error> {
+    session.userauth_pubkey_memory(username, public_key, private_key, passphrase)
+}
+
+// ssh2 only reads keys from memory on unix, and the Paste Key button says so everywhere else
+#[cfg(not(unix))]
+fn userauth_pubkey_memory(_session: &Session, _username: &str, _public_key: Option<&str>, _private_key: &str, _passphrase: Option<&str>) -> Result<(), ssh2::Error> {
+    Err(ssh2::Error::new(ssh2::ErrorCode::Session(-1), "Pasted keys are not supported on this platform"))
+}
+
+fn is_timeout(error: &ssh2::Error) -> bool {
+    error.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_environment_variables() {
+        assert_eq!(parse_environment_variable("LANG=C"), Some(("LANG".to_string(), "C".to_string())));
+        assert_eq!(parse_environment_variable("_PATH2=/a:/b"), Some(("_PATH2".to_string(), "/a:/b".to_string())));
+        assert_eq!(parse_environment_variable("EMPTY="), Some(("EMPTY".to_string(), String::new())));
+
+        // Only the first = separates the name, the rest belong to the value
+        assert_eq!(parse_environment_variable("OPTS=a=1,b=2"), Some(("OPTS".to_string(), "a=1,b=2".to_string())));
+    }
+
+    #[test]
+    fn rejects_bad_environment_variables() {
+        for pair in ["", "LANG", "=value", "2FAST=yes", "MY-VAR=1", "MY VAR=1", "$HOME=/"] {
+            assert_eq!(parse_environment_variable(pair), None, "{} should be rejected", pair);
+        }
+    }
+}
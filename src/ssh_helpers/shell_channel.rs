@@ -0,0 +1,540 @@
+use ssh2::Channel;
+use ssh2::PtyModeOpcode;
+use ssh2::PtyModes;
+use ssh2::Session;
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use std::thread;
+
+// A plain terminal type so programs don't send colours or cursor movement to the logger
+const TERMINAL_TYPE: &str = "dumb";
+const TERMINAL_SIZE: (u32, u32, u32, u32) = (200, 50, 0, 0);
+
+// Prompts would otherwise end up mixed into the output. PROMPT_COMMAND puts them back to empty
+// after anything like a venv activate script changes them
+pub const DEFAULT_SHELL_SETUP: &str = "stty -echo -onlcr 2>/dev/null; PS1=''; PS2=''; PROMPT=''; PROMPT_COMMAND='PS1=\"\"; PS2=\"\"'";
+
+// Stops one poll from holding up the worker when a command prints a lot at once
+const MAX_READS_PER_POLL: usize = 64;
+
+// A login script that needs input would otherwise stop the shell ever starting
+const SETUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How often run checks for more output while waiting for a command
+const RUN_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// How a new shell is prepared before any commands are typed into it
+#[derive(Clone)]
+pub struct ShellProfile {
+    // Run first, it should stop the shell printing prompts
+    pub setup: String,
+    // Sent with setenv, or exported if the server's AcceptEnv refuses them
+    pub environment: Vec<(String, String)>,
+    // Run after the setup, things like module load or conda activate
+    pub startup_commands: Vec<String>,
+}
+
+impl ShellProfile {
+    pub fn new() -> Self {
+        ShellProfile {
+            setup: DEFAULT_SHELL_SETUP.to_string(),
+            environment: Vec::new(),
+            startup_commands: Vec::new(),
+        }
+    }
+}
+
+// How a command went, once it has finished
+pub struct CommandResult {
+    // A PTY sends stderr through here too, so the two can't be told apart
+    pub stdout: String,
+    pub exit_status: i32,
+    // Shells report a command killed by a signal as 128 plus the signal's number, but a command can
+    // also exit with that status itself, so this is only a guess
+    pub probable_signal: Option<String>,
+    pub duration: Duration,
+}
+
+impl CommandResult {
+    pub fn succeeded(&self) -> bool {
+        self.exit_status == 0
+    }
+
+    // The line shown in the logger once the command is done
+    pub fn describe_exit(&self) -> String {
+        let seconds: f32 = self.duration.as_secs_f32();
+
+        match (&self.probable_signal, self.succeeded()) {
+            (Some(signal), _) => format!("Command Failed, exit status {} after {:.1}s, probably killed by {}", self.exit_status, seconds, signal),
+            (None, true) => format!("Command finished, exit status 0 after {:.1}s", seconds),
+            (None, false) => format!("Command Failed, exit status {} after {:.1}s", self.exit_status, seconds),
+        }
+    }
+}
+
+fn signal_name(exit_status: i32) -> Option<String> {
+    let name: &str = match exit_status - 128 {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        6 => "SIGABRT",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        15 => "SIGTERM",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+// New output from a running command since the last poll, and its result once it has finished
+pub struct ShellOutput {
+    pub stdout: Vec<String>,
+    // Only from servers that send stderr separately even with a PTY, it isn't kept in the CommandResult
+    pub stderr: Vec<String>,
+    pub result: Option<CommandResult>,
+}
+
+// Splits the shell's output into lines and picks out the marker line printed after each command
+struct MarkerParser {
+    // Printed after every command so we know where its output ends, along with which command it was
+    marker: String,
+    generation: u32,
+
+    // Output that doesn't make up a whole line yet
+    pending: Vec<u8>,
+    // An empty line is held back in case it's the one printed just before the marker
+    held_empty_line: bool,
+
+    // $PWD as of the last command to finish
+    working_directory: Option<String>,
+}
+
+impl MarkerParser {
+    fn new(marker: String) -> Self {
+        MarkerParser {
+            marker,
+            generation: 0,
+            pending: Vec::new(),
+            held_empty_line: false,
+            working_directory: None,
+        }
+    }
+
+    // Moves on to the next command, anything left from the last one is thrown away
+    fn start(&mut self) {
+        self.generation += 1;
+        self.pending.clear();
+        self.held_empty_line = false;
+    }
+
+    // Prints the marker, which command it ends, that command's exit status and the directory it left
+    // the shell in. The marker is printed in two halves so it never appears whole in an echo of this line
+    fn status_line(&self) -> String {
+        let (first_half, second_half) = self.marker.split_at(self.marker.len() / 2);
+
+        format!("printf '\\n%s%s %d %d %s\\n' '{}' '{}' {} \"$?\" \"$PWD\"\n", first_half, second_half, self.generation)
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    // Moves whole lines out of pending, returning the exit status if the marker line was among them
+    fn take_lines(&mut self, lines: &mut Vec<String>) -> Option<i32> {
+        let status_prefix: String = format!("{} ", self.marker);
+
+        while let Some(line_end) = self.pending.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.pending.drain(..=line_end).collect();
+            let line: String = String::from_utf8_lossy(&raw[..line_end]).trim_end_matches('\r').to_string();
+
+            if let Some(status_line) = line.strip_prefix(&status_prefix) {
+                // The directory goes last as it can have spaces in it
+                let mut fields = status_line.splitn(3, ' ');
+                let generation: Option<u32> = fields.next().and_then(|field| field.parse::<u32>().ok());
+                let status: i32 = fields.next().and_then(|field| field.parse::<i32>().ok()).unwrap_or(-1);
+
+                // A second marker from an interrupted command that finished anyway
+                if generation != Some(self.generation) {
+                    self.held_empty_line = false;
+                    continue;
+                }
+
+                if let Some(directory) = fields.next().filter(|directory| !directory.is_empty()) {
+                    self.working_directory = Some(directory.to_string());
+                }
+
+                // Nothing after the marker belongs to the command, and the held empty line came with the marker
+                self.pending.clear();
+                self.held_empty_line = false;
+                return Some(status);
+            }
+
+            if self.held_empty_line {
+                lines.push(String::new());
+            }
+
+            self.held_empty_line = line.is_empty();
+
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+
+        None
+    }
+}
+
+// One long-lived login shell on a PTY. Commands are written to its stdin like typing them into a
+// terminal, so cd, export, source and anything else stateful carries over to the next command
+pub struct ShellChannel {
+    session: Session,
+    channel: Channel,
+    parser: MarkerParser,
+
+    started: Option<Instant>,
+    // Stderr that doesn't make up a whole line yet
+    pending_stderr: Vec<u8>,
+
+    // Everything the running command has printed, for its CommandResult
+    stdout: Vec<String>,
+}
+
+impl ShellChannel {
+    // The session must be in blocking mode. The profile's startup commands are left to the caller
+    // so it can report any that fail
+    pub fn open(session: &Session, profile: &ShellProfile) -> Result<Self, String> {
+        let mut channel: Channel = session.channel_session()
+            .map_err(|e| format!("[SSH ERROR] Could not open a shell channel ({})", e.message()))?;
+
+        // Echo would repeat every command back, and onlcr turns every \n into \r\n
+        let mut modes = PtyModes::new();
+        modes.set_boolean(PtyModeOpcode::ECHO, false);
+        modes.set_boolean(PtyModeOpcode::ONLCR, false);
+
+        channel.request_pty(TERMINAL_TYPE, Some(modes), Some(TERMINAL_SIZE))
+            .map_err(|e| format!("[SSH ERROR] The server refused a terminal ({})", e.message()))?;
+
+        // Variables have to be set before the shell starts, most servers only accept a few like LANG
+        let refused: Vec<String> = profile.environment.iter()
+            .filter(|(name, value)| channel.setenv(name, value).is_err())
+            .map(|(name, value)| format!("export {}={}", name, shell_quote(value)))
+            .collect();
+
+        channel.shell()
+            .map_err(|e| format!("[SSH ERROR] The server refused to start a shell ({})", e.message()))?;
+
+        let nonce: u128 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos())
+            .unwrap_or_default();
+
+        let mut shell = ShellChannel {
+            session: session.clone(),
+            channel,
+            parser: MarkerParser::new(format!("__TRSSH_{}_{}__", std::process::id(), nonce)),
+            started: None,
+            pending_stderr: Vec::new(),
+            stdout: Vec::new(),
+        };
+
+        // Throws away the login banner along with anything the setup line prints
+        shell.run(&profile.setup, SETUP_TIMEOUT)?;
+
+        if !refused.is_empty() {
+            shell.run(&refused.join("; "), SETUP_TIMEOUT)?;
+        }
+
+        Ok(shell)
+    }
+
+    // Runs the command in the shell and waits for it to finish. If it takes longer than the timeout
+    // it is interrupted and left to finish, an error is returned and is_running stays true
+    pub fn run(&mut self, command: &str, timeout: Duration) -> Result<CommandResult, String> {
+        self.start(command)?;
+
+        loop {
+            if self.running_for() > timeout {
+                self.interrupt()?;
+                return Err(format!("[SSH TIMEOUT] '{}' took longer than {} seconds and was interrupted", command, timeout.as_secs()));
+            }
+
+            let output: ShellOutput = self.poll()?;
+
+            if let Some(result) = output.result {
+                return Ok(result);
+            }
+
+            if output.stdout.is_empty() && output.stderr.is_empty() {
+                thread::sleep(RUN_POLL_INTERVAL);
+            }
+        }
+    }
+
+    // Sends the command without waiting for it, its output then comes from poll
+    pub fn start(&mut self, command: &str) -> Result<(), String> {
+        self.parser.start();
+
+        // On its own line so the marker is still printed when the command is a syntax error
+        let input: String = format!("{}\n{}", command, self.parser.status_line());
+
+        self.channel.write_all(input.as_bytes())
+            .and_then(|_| self.channel.flush())
+            .map_err(|_| "[SSH ERROR] Could not send the command to the shell".to_string())?;
+
+        self.started = Some(Instant::now());
+        self.pending_stderr.clear();
+        self.stdout.clear();
+        Ok(())
+    }
+
+    // Sends Ctrl+C through the terminal. The terminal throws away anything typed ahead when it
+    // interrupts, which includes the marker line, so that is sent again
+    pub fn interrupt(&mut self) -> Result<(), String> {
+        let input: String = format!("\x03{}", self.parser.status_line());
+
+        self.channel.write_all(input.as_bytes())
+            .and_then(|_| self.channel.flush())
+            .map_err(|_| "[SSH ERROR] Could not interrupt the command".to_string())
+    }
+
+    pub fn working_directory(&self) -> Option<&str> {
+        self.parser.working_directory.as_deref()
+    }
+
+    pub fn running_for(&self) -> Duration {
+        self.started
+            .map(|started| started.elapsed())
+            .unwrap_or_default()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    // Returns the whole lines the command has printed since the last poll, and its result once it
+    // has finished. Only reads what has already arrived, so it never waits on the command
+    pub fn poll(&mut self) -> Result<ShellOutput, String> {
+        self.session.set_blocking(false);
+        let output = self.read_output();
+        self.session.set_blocking(true);
+
+        output
+    }
+
+    fn read_output(&mut self) -> Result<ShellOutput, String> {
+        let mut output = ShellOutput {
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            result: None,
+        };
+        let mut buffer = [0u8; 8192];
+
+        // Unread stderr would fill the channel's window and stall stdout behind it
+        while let Ok(n) = self.channel.stderr().read(&mut buffer)
+            && n > 0 {
+            self.pending_stderr.extend_from_slice(&buffer[..n]);
+        }
+
+        while let Some(line_end) = self.pending_stderr.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.pending_stderr.drain(..=line_end).collect();
+            output.stderr.push(String::from_utf8_lossy(&raw[..line_end]).trim_end_matches('\r').to_string());
+        }
+
+        for _ in 0..MAX_READS_PER_POLL {
+            match self.channel.read(&mut buffer) {
+                Ok(0) => {
+                    if self.channel.eof() {
+                        self.started = None;
+                        return Err("[SSH WARN] The shell exited, a new one will be started for the next command".to_string());
+                    }
+                    break;
+                }
+                Ok(n) => self.parser.push(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.started = None;
+                    return Err("[SSH ERROR] The channel was unable to read the result of your command.".to_string());
+                }
+            }
+
+            if let Some(exit_status) = self.parser.take_lines(&mut output.stdout) {
+                self.stdout.extend(output.stdout.iter().cloned());
+
+                let duration: Duration = self.started.take()
+                    .map(|started| started.elapsed())
+                    .unwrap_or_default();
+
+                output.result = Some(CommandResult {
+                    stdout: std::mem::take(&mut self.stdout).join("\n"),
+                    exit_status,
+                    probable_signal: signal_name(exit_status),
+                    duration,
+                });
+                return Ok(output);
+            }
+        }
+
+        self.stdout.extend(output.stdout.iter().cloned());
+        Ok(output)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.channel.eof()
+    }
+
+    pub fn close(&mut self) {
+        let _ = self.channel.send_eof();
+        let _ = self.channel.close();
+    }
+}
+
+// Single quotes keep spaces and anything else the shell would expand
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(exit_status: i32) -> CommandResult {
+        CommandResult {
+            stdout: String::new(),
+            exit_status,
+            probable_signal: signal_name(exit_status),
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    const MARKER: &str = "__TRSSH_1_2__";
+
+    fn started_parser() -> MarkerParser {
+        let mut parser = MarkerParser::new(MARKER.to_string());
+        parser.start();
+        parser
+    }
+
+    // What the shell prints for the status line, including the newline printf starts with
+    fn marker_output(generation: u32, status: i32, directory: &str) -> String {
+        format!("\n{} {} {} {}\n", MARKER, generation, status, directory)
+    }
+
+    #[test]
+    fn never_sends_the_marker_whole() {
+        let parser = started_parser();
+        let status_line: String = parser.status_line();
+
+        assert!(!status_line.contains(MARKER));
+        assert!(status_line.contains(" 1 \"$?\" \"$PWD\""));
+    }
+
+    #[test]
+    fn joins_lines_split_across_reads() {
+        let mut parser = started_parser();
+        let mut lines: Vec<String> = Vec::new();
+
+        parser.push(b"hel");
+        assert_eq!(parser.take_lines(&mut lines), None);
+        assert!(lines.is_empty());
+
+        parser.push(b"lo\r\nwor");
+        assert_eq!(parser.take_lines(&mut lines), None);
+        assert_eq!(lines, vec!["hello"]);
+
+        parser.push(format!("ld{}", marker_output(1, 0, "/home/alice")).as_bytes());
+        assert_eq!(parser.take_lines(&mut lines), Some(0));
+        assert_eq!(lines, vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn drops_only_the_empty_line_before_the_marker() {
+        let mut parser = started_parser();
+        let mut lines: Vec<String> = Vec::new();
+
+        parser.push(format!("one\n\ntwo\n{}", marker_output(1, 2, "/tmp")).as_bytes());
+
+        assert_eq!(parser.take_lines(&mut lines), Some(2));
+        assert_eq!(lines, vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn ignores_output_after_the_marker() {
+        let mut parser = started_parser();
+        let mut lines: Vec<String> = Vec::new();
+
+        parser.push(format!("{}left over\n", marker_output(1, 0, "/tmp")).as_bytes());
+
+        assert_eq!(parser.take_lines(&mut lines), Some(0));
+        assert!(lines.is_empty());
+        assert_eq!(parser.take_lines(&mut lines), None);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn skips_markers_from_earlier_commands() {
+        let mut parser = started_parser();
+        parser.start();
+        let mut lines: Vec<String> = Vec::new();
+
+        // An interrupted command prints its marker twice, once for the command and once for the interrupt
+        parser.push(format!("{}{}", marker_output(1, 130, "/old"), marker_output(1, 130, "/old")).as_bytes());
+        assert_eq!(parser.take_lines(&mut lines), None);
+
+        parser.push(format!("new{}", marker_output(2, 0, "/new")).as_bytes());
+        assert_eq!(parser.take_lines(&mut lines), Some(0));
+        assert_eq!(lines, vec!["new"]);
+        assert_eq!(parser.working_directory.as_deref(), Some("/new"));
+    }
+
+    #[test]
+    fn tracks_the_working_directory() {
+        let mut parser = started_parser();
+        let mut lines: Vec<String> = Vec::new();
+
+        parser.push(marker_output(1, 0, "/home/alice/My Documents").as_bytes());
+        assert_eq!(parser.take_lines(&mut lines), Some(0));
+        assert_eq!(parser.working_directory.as_deref(), Some("/home/alice/My Documents"));
+
+        // An empty $PWD keeps the last directory
+        parser.start();
+        parser.push(marker_output(2, 1, "").as_bytes());
+        assert_eq!(parser.take_lines(&mut lines), Some(1));
+        assert_eq!(parser.working_directory.as_deref(), Some("/home/alice/My Documents"));
+    }
+
+    #[test]
+    fn reads_a_garbled_status_as_failed() {
+        let mut parser = started_parser();
+        let mut lines: Vec<String> = Vec::new();
+
+        parser.push(format!("\n{} 1 oops /tmp\n", MARKER).as_bytes());
+
+        assert_eq!(parser.take_lines(&mut lines), Some(-1));
+    }
+
+    #[test]
+    fn names_signals_from_exit_statuses() {
+        assert_eq!(signal_name(130).as_deref(), Some("SIGINT"));
+        assert_eq!(signal_name(137).as_deref(), Some("SIGKILL"));
+        assert_eq!(signal_name(143).as_deref(), Some("SIGTERM"));
+        assert_eq!(signal_name(0), None);
+        assert_eq!(signal_name(2), None);
+        assert_eq!(signal_name(128), None);
+        assert_eq!(signal_name(255), None);
+    }
+
+    #[test]
+    fn only_guesses_at_signals() {
+        assert_eq!(result(0).describe_exit(), "Command finished, exit status 0 after 1.5s");
+        assert_eq!(result(1).describe_exit(), "Command Failed, exit status 1 after 1.5s");
+        assert_eq!(result(130).describe_exit(), "Command Failed, exit status 130 after 1.5s, probably killed by SIGINT");
+    }
+}
@@ -0,0 +1,451 @@
+use ssh2::Session;
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::ssh_helpers::channel_pump::ChannelBridge;
+use crate::ssh_helpers::channel_pump::ChannelOpener;
+use crate::ssh_helpers::port_forwarding::format_bytes;
+use crate::ssh_helpers::port_forwarding::parse_port;
+use crate::ssh_helpers::port_forwarding::pump_bridges;
+use crate::ssh_helpers::port_forwarding::split_forward_spec;
+
+pub const DEFAULT_SOCKS_PORT: u16 = 1080;
+
+// Clients that connect but never finish asking for somewhere are dropped after this long
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const COMMAND_CONNECT: u8 = 1;
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+// Reply codes from RFC 1928
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_GENERAL_FAILURE: u8 = 1;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+// Same format as ssh -D: [bind_address:]port
+pub struct SocksSpec {
+    pub bind_address: String,
+    pub port: u16,
+}
+
+impl SocksSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = split_forward_spec(spec.trim());
+
+        let (bind_address, port) = match parts.as_slice() {
+            [""] => ("127.0.0.1", DEFAULT_SOCKS_PORT),
+            [port] => ("127.0.0.1", parse_port(port)?),
+            [bind, port] => (*bind, parse_port(port)?),
+            _ => return Err(format!("[SSH SOCKS] '{}' should look like [bind_address:]port", spec)),
+        };
+
+        Ok(SocksSpec {
+            bind_address: bind_address.to_string(),
+            port,
+        })
+    }
+}
+
+// What a client's bytes so far add up to
+enum HandshakeStep {
+    NeedMore,
+    Connect(String, u16),
+    Failed(u8, String),
+}
+
+// A client that has connected but not yet said where it wants to go
+struct PendingClient {
+    socket: TcpStream,
+    peer: SocketAddr,
+    buffer: Vec<u8>,
+    greeted: bool,
+    started: Instant,
+    // Where it asked to go, once it's waiting on the server to open a channel there
+    target: Option<(String, u16)>,
+}
+
+impl PendingClient {
+    // Reads whatever has arrived and works out what to do next
+    fn read_step(&mut self) -> HandshakeStep {
+        let mut chunk = [0u8; 512];
+
+        match self.socket.read(&mut chunk) {
+            Ok(0) => return HandshakeStep::Failed(REPLY_GENERAL_FAILURE, "closed before asking for anywhere".to_string()),
+            Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return HandshakeStep::Failed(REPLY_GENERAL_FAILURE, e.to_string()),
+        }
+
+        if !self.greeted {
+            let greeting_length: usize = match parse_greeting(&self.buffer) {
+                Ok(length) => length,
+                Err(step) => return step,
+            };
+
+            // A client can send its request in the same write as its greeting, so only the greeting is dropped
+            self.buffer.drain(..greeting_length);
+            self.greeted = true;
+
+            if let Err(e) = self.socket.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION]) {
+                return HandshakeStep::Failed(REPLY_GENERAL_FAILURE, e.to_string());
+            }
+        }
+
+        let (host, port, request_length) = match parse_request(&self.buffer) {
+            Ok(request) => request,
+            Err(step) => return step,
+        };
+
+        // Anything after the request was sent ahead of our reply, and is left for the channel
+        self.buffer.drain(..request_length);
+
+        HandshakeStep::Connect(host, port)
+    }
+}
+
+// VER NMETHODS METHODS..., returns how long the greeting was once it has all arrived
+fn parse_greeting(buffer: &[u8]) -> Result<usize, HandshakeStep> {
+    if buffer.len() < 2 || buffer.len() < 2 + buffer[1] as usize {
+        return Err(HandshakeStep::NeedMore);
+    }
+
+    if buffer[0] != SOCKS_VERSION {
+        return Err(HandshakeStep::Failed(REPLY_GENERAL_FAILURE, format!("SOCKS version {} isn't supported", buffer[0])));
+    }
+
+    let length: usize = 2 + buffer[1] as usize;
+
+    if !buffer[2..length].contains(&NO_AUTHENTICATION) {
+        return Err(HandshakeStep::Failed(NO_ACCEPTABLE_METHODS, "the client wants authentication".to_string()));
+    }
+
+    Ok(length)
+}
+
+// VER CMD RSV ATYP DST.ADDR DST.PORT, returns where to connect to and how long the request was
+fn parse_request(buffer: &[u8]) -> Result<(String, u16, usize), HandshakeStep> {
+    if let Some(version) = buffer.first() && *version != SOCKS_VERSION {
+        return Err(HandshakeStep::Failed(REPLY_GENERAL_FAILURE, format!("SOCKS version {} isn't supported", version)));
+    }
+
+    if buffer.len() < 5 {
+        return Err(HandshakeStep::NeedMore);
+    }
+
+    if buffer[1] != COMMAND_CONNECT {
+        return Err(HandshakeStep::Failed(REPLY_COMMAND_NOT_SUPPORTED, format!("command {} isn't supported, only CONNECT", buffer[1])));
+    }
+
+    let (host, address_end): (String, usize) = match buffer[3] {
+        ADDRESS_IPV4 => {
+            if buffer.len() < 10 {
+                return Err(HandshakeStep::NeedMore);
+            }
+            (Ipv4Addr::new(buffer[4], buffer[5], buffer[6], buffer[7]).to_string(), 8)
+        }
+        ADDRESS_DOMAIN => {
+            let end: usize = 5 + buffer[4] as usize;
+            if buffer.len() < end + 2 {
+                return Err(HandshakeStep::NeedMore);
+            }
+            (String::from_utf8_lossy(&buffer[5..end]).to_string(), end)
+        }
+        ADDRESS_IPV6 => {
+            if buffer.len() < 22 {
+                return Err(HandshakeStep::NeedMore);
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&buffer[4..20]);
+            (Ipv6Addr::from(octets).to_string(), 20)
+        }
+        other => return Err(HandshakeStep::Failed(REPLY_ADDRESS_NOT_SUPPORTED, format!("address type {} isn't supported", other))),
+    };
+
+    let port: u16 = u16::from_be_bytes([buffer[address_end], buffer[address_end + 1]]);
+
+    Ok((host, port, address_end + 2))
+}
+
+// The bound address isn't known through a direct-tcpip channel, so it's always reported as 0.0.0.0:0
+fn send_reply(socket: &mut TcpStream, code: u8) -> std::io::Result<()> {
+    socket.write_all(&[SOCKS_VERSION, code, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+}
+
+// Listens locally like ssh -D, and sends each connection wherever the client asks through the server
+pub struct SocksProxy {
+    pub local_address: SocketAddr,
+
+    listener: TcpListener,
+    pending: Vec<PendingClient>,
+    connections: Vec<ChannelBridge>,
+
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl SocksProxy {
+    pub fn open(spec: &SocksSpec) -> Result<Self, String> {
+        let address: SocketAddr = (spec.bind_address.as_str(), spec.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or(format!("[SSH SOCKS] Could not resolve {}", spec.bind_address))?;
+
+        let listener = TcpListener::bind(address)
+            .map_err(|e| format!("[SSH SOCKS] Could not listen on {} ({})", address, e))?;
+
+        listener.set_nonblocking(true)
+            .map_err(|_| "[SSH SOCKS] Could not make the listener non-blocking".to_string())?;
+
+        Ok(SocksProxy {
+            local_address: listener.local_addr().unwrap_or(address),
+            listener,
+            pending: Vec::new(),
+            connections: Vec::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+        })
+    }
+
+    // Accepts new clients, moves their handshakes along, and moves data for the connected ones.
+    // Returns lines for the logger. The session must be in blocking mode, like LocalForward::poll
+    pub fn poll(&mut self, session: &Session, opener: &mut ChannelOpener) -> Vec<String> {
+        let mut log_lines: Vec<String> = Vec::new();
+
+        while let Ok((socket, peer)) = self.listener.accept() {
+            if socket.set_nonblocking(true).is_err() {
+                log_lines.push(format!("[SSH SOCKS] Dropped {}, could not make its socket non-blocking", peer));
+                continue;
+            }
+
+            self.pending.push(PendingClient {
+                socket,
+                peer,
+                buffer: Vec::new(),
+                greeted: false,
+                started: Instant::now(),
+                target: None,
+            });
+        }
+
+        session.set_blocking(false);
+
+        let mut still_pending: Vec<PendingClient> = Vec::new();
+
+        for mut client in self.pending.drain(..) {
+            // Nothing more is read from a client until its channel is open
+            let step: HandshakeStep = match client.target.take() {
+                Some((host, port)) => HandshakeStep::Connect(host, port),
+                None => client.read_step(),
+            };
+
+            match step {
+                HandshakeStep::NeedMore => {
+                    if client.started.elapsed() > HANDSHAKE_TIMEOUT {
+                        log_lines.push(format!("[SSH SOCKS] Dropped {}, it never finished the handshake", client.peer));
+                    } else {
+                        still_pending.push(client);
+                    }
+                }
+                HandshakeStep::Failed(code, reason) => {
+                    if client.greeted {
+                        let _ = send_reply(&mut client.socket, code);
+                    } else {
+                        let _ = client.socket.write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS]);
+                    }
+                    log_lines.push(format!("[SSH SOCKS] Refused {}: {}", client.peer, reason));
+                }
+                HandshakeStep::Connect(host, port) => {
+                    match opener.open_direct_tcpip(session, &host, port) {
+                        Ok(None) => {
+                            client.target = Some((host, port));
+                            still_pending.push(client);
+                        }
+                        Ok(Some(channel)) => {
+                            let bridged = send_reply(&mut client.socket, REPLY_SUCCEEDED)
+                                .map_err(|e| e.to_string())
+                                .and_then(|_| ChannelBridge::new(channel, client.socket))
+                                .map(|mut bridge| {
+                                    bridge.queue_to_channel(&client.buffer);
+                                    bridge
+                                });
+
+                            match bridged {
+                                Ok(bridge) => {
+                                    self.connections.push(bridge);
+                                    log_lines.push(format!("[SSH SOCKS] {} -> {}:{}", client.peer, host, port));
+                                }
+                                Err(message) => log_lines.push(format!("[SSH SOCKS] {} -> {}:{} failed: {}", client.peer, host, port, message)),
+                            }
+                        }
+                        Err(message) => {
+                            let _ = send_reply(&mut client.socket, REPLY_GENERAL_FAILURE);
+                            log_lines.push(format!("[SSH SOCKS] {} -> {}:{} refused by the server: {}", client.peer, host, port, message));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending = still_pending;
+
+        let (sent, received) = pump_bridges(&mut self.connections);
+        self.bytes_sent += sent;
+        self.bytes_received += received;
+
+        session.set_blocking(true);
+
+        log_lines
+    }
+
+    pub fn describe(&self) -> String {
+        format!("-D {} SOCKS5  {} open  {} up / {} down",
+            self.local_address,
+            self.connections.len(),
+            format_bytes(self.bytes_sent),
+            format_bytes(self.bytes_received))
+    }
+
+    pub fn close(&mut self) {
+        for bridge in &mut self.connections {
+            bridge.close();
+        }
+        self.connections.clear();
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CONNECT to example.com:443 by name
+    const DOMAIN_REQUEST: [u8; 18] = [5, 1, 0, 3, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm', 1, 187];
+
+    #[test]
+    fn parses_specs() {
+        let default = SocksSpec::parse("").unwrap();
+        assert_eq!((default.bind_address.as_str(), default.port), ("127.0.0.1", DEFAULT_SOCKS_PORT));
+
+        let bound = SocksSpec::parse("[::1]:9050").unwrap();
+        assert_eq!((bound.bind_address.as_str(), bound.port), ("::1", 9050));
+
+        assert!(SocksSpec::parse("a:b:c").is_err());
+    }
+
+    #[test]
+    fn waits_for_the_whole_greeting() {
+        assert!(matches!(parse_greeting(&[5]), Err(HandshakeStep::NeedMore)));
+        assert!(matches!(parse_greeting(&[5, 2, 0]), Err(HandshakeStep::NeedMore)));
+        assert!(matches!(parse_greeting(&[5, 2, 2, 0]), Ok(4)));
+    }
+
+    #[test]
+    fn rejects_bad_greetings() {
+        assert!(matches!(parse_greeting(&[4, 1, 0]), Err(HandshakeStep::Failed(REPLY_GENERAL_FAILURE, _))));
+        assert!(matches!(parse_greeting(&[5, 1, 2]), Err(HandshakeStep::Failed(NO_ACCEPTABLE_METHODS, _))));
+    }
+
+    #[test]
+    fn parses_each_address_type() {
+        match parse_request(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 22]) {
+            Ok((host, port, length)) => assert_eq!((host.as_str(), port, length), ("10.0.0.1", 22, 10)),
+            _ => panic!("IPv4 request wasn't parsed"),
+        }
+
+        match parse_request(&DOMAIN_REQUEST) {
+            Ok((host, port, length)) => assert_eq!((host.as_str(), port, length), ("example.com", 443, DOMAIN_REQUEST.len())),
+            _ => panic!("domain request wasn't parsed"),
+        }
+
+        let mut ipv6_request: Vec<u8> = vec![5, 1, 0, 4];
+        ipv6_request.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ipv6_request.extend_from_slice(&8080u16.to_be_bytes());
+
+        match parse_request(&ipv6_request) {
+            Ok((host, port, length)) => assert_eq!((host.as_str(), port, length), ("::1", 8080, 22)),
+            _ => panic!("IPv6 request wasn't parsed"),
+        }
+    }
+
+    #[test]
+    fn waits_for_the_whole_request() {
+        for length in 0..DOMAIN_REQUEST.len() {
+            assert!(matches!(parse_request(&DOMAIN_REQUEST[..length]), Err(HandshakeStep::NeedMore)), "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        assert!(matches!(parse_request(&[4, 1, 0, 1, 10, 0, 0, 1, 0, 22]), Err(HandshakeStep::Failed(REPLY_GENERAL_FAILURE, _))));
+        assert!(matches!(parse_request(&[4]), Err(HandshakeStep::Failed(REPLY_GENERAL_FAILURE, _))));
+        assert!(matches!(parse_request(&[5, 2, 0, 1, 10, 0, 0, 1, 0, 22]), Err(HandshakeStep::Failed(REPLY_COMMAND_NOT_SUPPORTED, _))));
+        assert!(matches!(parse_request(&[5, 1, 0, 9, 0]), Err(HandshakeStep::Failed(REPLY_ADDRESS_NOT_SUPPORTED, _))));
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_request() {
+        let mut with_payload: Vec<u8> = DOMAIN_REQUEST.to_vec();
+        with_payload.extend_from_slice(b"GET / HTTP/1.0\r\n\r\n");
+
+        match parse_request(&with_payload) {
+            Ok((host, port, length)) => assert_eq!((host.as_str(), port, length), ("example.com", 443, DOMAIN_REQUEST.len())),
+            _ => panic!("request with a payload wasn't parsed"),
+        }
+    }
+
+    #[test]
+    fn handles_a_request_sent_with_the_greeting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, peer) = listener.accept().unwrap();
+
+        // Greeting, request and the first of the data all in one write
+        let mut pipelined: Vec<u8> = vec![5, 1, 0];
+        pipelined.extend_from_slice(&DOMAIN_REQUEST);
+        pipelined.extend_from_slice(b"GET / HTTP/1.0\r\n\r\n");
+        client.write_all(&pipelined).unwrap();
+
+        let mut pending = PendingClient {
+            socket,
+            peer,
+            buffer: Vec::new(),
+            greeted: false,
+            started: Instant::now(),
+            target: None,
+        };
+
+        let mut step: HandshakeStep = pending.read_step();
+        while matches!(step, HandshakeStep::NeedMore) {
+            step = pending.read_step();
+        }
+
+        match step {
+            HandshakeStep::Connect(host, port) => assert_eq!((host.as_str(), port), ("example.com", 443)),
+            _ => panic!("pipelined request was lost"),
+        }
+
+        // Left for the channel once it's open
+        assert_eq!(pending.buffer, b"GET / HTTP/1.0\r\n\r\n");
+
+        let mut method_reply = [0u8; 2];
+        client.read_exact(&mut method_reply).unwrap();
+        assert_eq!(method_reply, [SOCKS_VERSION, NO_AUTHENTICATION]);
+    }
+}
@@ -0,0 +1,564 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ssh_helpers::ssh_directory;
+use crate::ssh_helpers::algorithms::AlgorithmPreferences;
+
+// OpenSSH gives up on Include chains this deep, which also stops a file including itself forever
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub fn default_config_path() -> Option<PathBuf> {
+    ssh_directory().map(|dir| dir.join("config"))
+}
+
+// A Host line and every option under it until the next Host or Match line
+struct HostBlock {
+    patterns: Vec<String>,
+    // Keys are lowercased as ssh_config keywords are case-insensitive
+    options: Vec<(String, String)>,
+}
+
+// Everything ~/.ssh/config has to say about one host alias
+#[derive(Clone, Debug, Default)]
+pub struct ResolvedHost {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<String>,
+    pub certificate_files: Vec<String>,
+    pub identities_only: bool,
+    pub proxy_jump: Option<String>,
+    // Seconds
+    pub connect_timeout: Option<u64>,
+    // NAME=value pairs from SetEnv
+    pub environment: Vec<String>,
+    // trssh's own keywords, ssh needs "IgnoreUnknown Trssh*" to accept them
+    pub startup_commands: Vec<String>,
+    pub shell_setup: Option<String>,
+    // Seconds, from TrsshCommandTimeout and TrsshTransferTimeout
+    pub command_timeout: Option<u64>,
+    pub transfer_timeout: Option<u64>,
+    // KexAlgorithms, HostKeyAlgorithms, Ciphers and MACs
+    pub algorithms: AlgorithmPreferences,
+}
+
+pub struct SshConfig {
+    blocks: Vec<HostBlock>,
+}
+
+impl SshConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        // Options before the first Host line apply to every host
+        let mut blocks: Vec<HostBlock> = vec![HostBlock {
+            patterns: vec!["*".to_string()],
+            options: Vec::new(),
+        }];
+
+        read_config_file(path, &mut blocks, 0)?;
+
+        Ok(SshConfig { blocks })
+    }
+
+    // Like ssh, the first value found for an option wins, apart from IdentityFile, CertificateFile and startup commands which add up
+    pub fn resolve(&self, alias: &str) -> ResolvedHost {
+        let mut resolved = ResolvedHost::default();
+        let mut identities_only: Option<bool> = None;
+
+        for block in self.blocks.iter().filter(|block| host_matches(&block.patterns, alias)) {
+            for (key, value) in &block.options {
+                match key.as_str() {
+                    "hostname" if resolved.hostname.is_none() => {
+                        resolved.hostname = Some(value.clone());
+                    }
+                    "user" if resolved.user.is_none() => {
+                        resolved.user = Some(value.clone());
+                    }
+                    "port" if resolved.port.is_none() => {
+                        resolved.port = value.parse::<u16>().ok();
+                    }
+                    "proxyjump" if resolved.proxy_jump.is_none() => {
+                        resolved.proxy_jump = Some(value.clone());
+                    }
+                    "connecttimeout" if resolved.connect_timeout.is_none() => {
+                        resolved.connect_timeout = value.parse::<u64>().ok();
+                    }
+                    "identityfile" => {
+                        resolved.identity_files.push(value.clone());
+                    }
+                    "certificatefile" => {
+                        resolved.certificate_files.push(value.clone());
+                    }
+                    // Each variable keeps the first value given for it
+                    "setenv" => {
+                        for pair in split_arguments(value) {
+                            let name: &str = pair.split('=').next().unwrap_or_default();
+
+                            if !resolved.environment.iter().any(|known| known.split('=').next() == Some(name)) {
+                                resolved.environment.push(pair);
+                            }
+                        }
+                    }
+                    "trsshstartupcommand" => {
+                        resolved.startup_commands.push(value.clone());
+                    }
+                    "trsshshellsetup" if resolved.shell_setup.is_none() => {
+                        resolved.shell_setup = Some(value.clone());
+                    }
+                    "trsshcommandtimeout" if resolved.command_timeout.is_none() => {
+                        resolved.command_timeout = value.parse::<u64>().ok();
+                    }
+                    "trsshtransfertimeout" if resolved.transfer_timeout.is_none() => {
+                        resolved.transfer_timeout = value.parse::<u64>().ok();
+                    }
+                    "kexalgorithms" if resolved.algorithms.kex.is_none() => {
+                        resolved.algorithms.kex = Some(value.clone());
+                    }
+                    "hostkeyalgorithms" if resolved.algorithms.host_key.is_none() => {
+                        resolved.algorithms.host_key = Some(value.clone());
+                    }
+                    "ciphers" if resolved.algorithms.ciphers.is_none() => {
+                        resolved.algorithms.ciphers = Some(value.clone());
+                    }
+                    "macs" if resolved.algorithms.macs.is_none() => {
+                        resolved.algorithms.macs = Some(value.clone());
+                    }
+                    "identitiesonly" if identities_only.is_none() => {
+                        identities_only = Some(value.eq_ignore_ascii_case("yes"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        resolved.identities_only = identities_only.unwrap_or(false);
+
+        // HostName can refer back to the alias with %h
+        if let Some(hostname) = resolved.hostname.take() {
+            resolved.hostname = Some(hostname.replace("%h", alias));
+        }
+
+        let hostname: String = resolved.hostname.clone().unwrap_or(alias.to_string());
+        let user: String = resolved.user.clone().unwrap_or_default();
+
+        resolved.identity_files = resolved.identity_files.iter()
+            .map(|file| expand_path_tokens(file, &hostname, &user))
+            .collect();
+        resolved.certificate_files = resolved.certificate_files.iter()
+            .map(|file| expand_path_tokens(file, &hostname, &user))
+            .collect();
+
+        resolved
+    }
+}
+
+fn read_config_file(path: &Path, blocks: &mut Vec<HostBlock>, depth: usize) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!("[SSH CONFIG] Too many nested Includes reading {}", path.display()));
+    }
+
+    let contents: String = fs::read_to_string(path)
+        .map_err(|_| format!("[SSH CONFIG] Could not read {}", path.display()))?;
+
+    for line in contents.lines() {
+        let Some((key, value)) = split_config_line(line) else {
+            continue;
+        };
+
+        match key.as_str() {
+            "host" => {
+                blocks.push(HostBlock {
+                    patterns: value.split_whitespace().map(|p| p.to_string()).collect(),
+                    options: Vec::new(),
+                });
+            }
+            // Match criteria aren't supported, so nothing under one is applied
+            "match" => {
+                blocks.push(HostBlock {
+                    patterns: Vec::new(),
+                    options: Vec::new(),
+                });
+            }
+            // The included lines carry on in whichever block the Include was in
+            "include" => {
+                let block_count: usize = blocks.len();
+                let parent_patterns: Vec<String> = blocks.last()
+                    .map(|block| block.patterns.clone())
+                    .unwrap_or_default();
+
+                for pattern in value.split_whitespace() {
+                    for included in expand_include(pattern) {
+                        read_config_file(&included, blocks, depth + 1)?;
+                    }
+                }
+
+                // Lines after the Include belong to the block it was in, not the included file's last one
+                if blocks.len() != block_count {
+                    blocks.push(HostBlock {
+                        patterns: parent_patterns,
+                        options: Vec::new(),
+                    });
+                }
+            }
+            _ => {
+                if let Some(block) = blocks.last_mut() {
+                    block.options.push((key, unquote(&value)));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Lines look like "Key value" or "Key=value", returns None for blanks and comments
+fn split_config_line(line: &str) -> Option<(String, String)> {
+    let line: &str = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let split_index: usize = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let key: String = line[..split_index].to_lowercase();
+    let value: String = line[split_index..]
+        .trim_start_matches(|c: char| c.is_whitespace() || c == '=')
+        .trim()
+        .to_string();
+
+    Some((key, value))
+}
+
+fn unquote(value: &str) -> String {
+    value.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+// Splits on whitespace, apart from inside double quotes, like "NAME=a b"
+fn split_arguments(value: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut current: String = String::new();
+    let mut quoted: bool = false;
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+
+    arguments
+}
+
+// Relative includes are relative to ~/.ssh, and the file name may contain wildcards
+fn expand_include(pattern: &str) -> Vec<PathBuf> {
+    let expanded: PathBuf = PathBuf::from(expand_home(&unquote(pattern)));
+    let path: PathBuf = if expanded.is_absolute() {
+        expanded
+    } else {
+        match ssh_directory() {
+            Some(dir) => dir.join(expanded),
+            None => return Vec::new(),
+        }
+    };
+
+    let file_pattern: String = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if !file_pattern.contains('*') && !file_pattern.contains('?') {
+        return vec![path];
+    }
+
+    let Some(parent) = path.parent() else {
+        return Vec::new();
+    };
+
+    let mut matches: Vec<PathBuf> = fs::read_dir(parent)
+        .map(|entries| entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|p| p.is_file())
+            .filter(|p| p.file_name()
+                .is_some_and(|name| wildcard_match(&file_pattern, &name.to_string_lossy())))
+            .collect())
+        .unwrap_or_default();
+
+    // ssh reads globbed includes in lexical order
+    matches.sort();
+    matches
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+// The tokens ssh allows in IdentityFile and CertificateFile: %d home, %h hostname, %r remote user, %% a literal %
+fn expand_path_tokens(path: &str, hostname: &str, user: &str) -> String {
+    let home: String = std::env::home_dir()
+        .map(|home| home.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let expanded: String = expand_home(path)
+        .replace("%%", "\0")
+        .replace("%d", &home)
+        .replace("%h", hostname)
+        .replace("%r", user)
+        .replace('\0', "%");
+
+    expanded
+}
+
+// A host matches if any of the patterns match and none of the negated ones do
+fn host_matches(patterns: &[String], host: &str) -> bool {
+    let mut matched: bool = false;
+
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if wildcard_match(negated, host) {
+                return false;
+            }
+        } else if wildcard_match(pattern, host) {
+            matched = true;
+        }
+    }
+
+    matched
+}
+
+// * matches any run of characters and ? matches exactly one, ignoring case like ssh does
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            // Let the last * swallow one more character and try again
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes the config and anything it includes into a directory of its own, then loads it
+    fn load_config(name: &str, files: &[(&str, &str)]) -> SshConfig {
+        let dir: PathBuf = std::env::temp_dir().join(format!("trssh-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents.replace("$DIR", &dir.to_string_lossy())).unwrap();
+        }
+
+        let config: SshConfig = SshConfig::load(&dir.join(files[0].0)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        config
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*.example.com", "web.example.com"));
+        assert!(wildcard_match("WEB?", "web1"));
+        assert!(wildcard_match("a*b*c", "axxbyybc"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+        assert!(!wildcard_match("web?", "web"));
+        assert!(!wildcard_match("web", "web1"));
+    }
+
+    #[test]
+    fn splits_lines() {
+        assert_eq!(split_config_line("  HostName = example.com"), Some(("hostname".to_string(), "example.com".to_string())));
+        assert_eq!(split_config_line("User\talice"), Some(("user".to_string(), "alice".to_string())));
+        assert_eq!(split_config_line("# comment"), None);
+        assert_eq!(split_config_line("   "), None);
+    }
+
+    #[test]
+    fn first_value_wins() {
+        let config: SshConfig = load_config("first", &[("config", "
+Host web
+    HostName %h.example.com
+    User alice
+    IdentityFile /keys/%r@%h
+    IdentitiesOnly yes
+Host *
+    User nobody
+    Port 2200
+    IdentityFile /keys/shared
+    IdentitiesOnly no
+")]);
+
+        let web: ResolvedHost = config.resolve("web");
+        assert_eq!(web.hostname.as_deref(), Some("web.example.com"));
+        assert_eq!(web.user.as_deref(), Some("alice"));
+        assert_eq!(web.port, Some(2200));
+        assert_eq!(web.identity_files, vec!["/keys/alice@web.example.com", "/keys/shared"]);
+        assert!(web.identities_only);
+
+        let other: ResolvedHost = config.resolve("other");
+        assert_eq!(other.hostname, None);
+        assert_eq!(other.user.as_deref(), Some("nobody"));
+        assert!(!other.identities_only);
+    }
+
+    #[test]
+    fn negated_patterns_exclude_hosts() {
+        let config: SshConfig = load_config("negation", &[("config", "
+Host *.internal !bastion.internal
+    User deploy
+")]);
+
+        assert_eq!(config.resolve("db.internal").user.as_deref(), Some("deploy"));
+        assert_eq!(config.resolve("bastion.internal").user, None);
+        assert_eq!(config.resolve("db.external").user, None);
+    }
+
+    #[test]
+    fn reads_included_files() {
+        let config: SshConfig = load_config("include", &[
+            ("config", "
+Host db
+    Include $DIR/included
+    User after-include
+Host *
+    User fallback
+"),
+            ("included", "
+Port 2200
+Host cache
+    HostName cache.example.com
+"),
+        ]);
+
+        let db: ResolvedHost = config.resolve("db");
+        assert_eq!(db.port, Some(2200));
+        assert_eq!(db.user.as_deref(), Some("after-include"));
+        assert_eq!(db.hostname, None);
+
+        // Lines before the included file's first Host only apply inside the block that included it
+        let cache: ResolvedHost = config.resolve("cache");
+        assert_eq!(cache.port, None);
+        assert_eq!(cache.hostname.as_deref(), Some("cache.example.com"));
+        assert_eq!(cache.user.as_deref(), Some("fallback"));
+    }
+
+    #[test]
+    fn ignores_match_blocks() {
+        let config: SshConfig = load_config("match", &[("config", "
+Match user root
+    Port 2222
+Host *
+    Port 22
+")]);
+
+        assert_eq!(config.resolve("anything").port, Some(22));
+    }
+
+    #[test]
+    fn reads_shell_options() {
+        let config: SshConfig = load_config("shell", &[("config", "
+Host hpc
+    SetEnv LANG=C \"GREETING=hello there\"
+    SetEnv LANG=en_GB.UTF-8
+    TrsshStartupCommand module load gcc
+    TrsshStartupCommand cd /scratch
+    TrsshShellSetup set +o history
+Host *
+    TrsshShellSetup stty -echo
+")]);
+
+        let hpc: ResolvedHost = config.resolve("hpc");
+        assert_eq!(hpc.environment, vec!["LANG=C", "GREETING=hello there"]);
+        assert_eq!(hpc.startup_commands, vec!["module load gcc", "cd /scratch"]);
+        assert_eq!(hpc.shell_setup.as_deref(), Some("set +o history"));
+    }
+
+    #[test]
+    fn collects_certificate_files() {
+        let config: SshConfig = load_config("certificates", &[("config", "
+Host web
+    CertificateFile /keys/%r-cert.pub
+    User alice
+Host *
+    CertificateFile /keys/shared-cert.pub
+")]);
+
+        assert_eq!(config.resolve("web").certificate_files, vec!["/keys/alice-cert.pub", "/keys/shared-cert.pub"]);
+    }
+
+    #[test]
+    fn reads_algorithm_lists() {
+        let config: SshConfig = load_config("algorithms", &[("config", "
+Host old-router
+    KexAlgorithms +diffie-hellman-group1-sha1
+    HostKeyAlgorithms +ssh-rsa
+Host *
+    KexAlgorithms -diffie-hellman-*
+    Ciphers ^aes256-ctr
+    MACs hmac-sha2-256,hmac-sha2-512
+")]);
+
+        let router: ResolvedHost = config.resolve("old-router");
+        assert_eq!(router.algorithms.kex.as_deref(), Some("+diffie-hellman-group1-sha1"));
+        assert_eq!(router.algorithms.host_key.as_deref(), Some("+ssh-rsa"));
+        assert_eq!(router.algorithms.ciphers.as_deref(), Some("^aes256-ctr"));
+        assert_eq!(router.algorithms.macs.as_deref(), Some("hmac-sha2-256,hmac-sha2-512"));
+
+        assert_eq!(config.resolve("other").algorithms.kex.as_deref(), Some("-diffie-hellman-*"));
+    }
+
+    #[test]
+    fn reads_timeouts() {
+        let config: SshConfig = load_config("timeouts", &[("config", "
+Host hpc
+    ConnectTimeout 5
+    TrsshCommandTimeout 30
+    TrsshTransferTimeout 120
+Host *
+    ConnectTimeout 20
+    TrsshCommandTimeout soon
+")]);
+
+        let hpc: ResolvedHost = config.resolve("hpc");
+        assert_eq!(hpc.connect_timeout, Some(5));
+        assert_eq!(hpc.command_timeout, Some(30));
+        assert_eq!(hpc.transfer_timeout, Some(120));
+
+        let other: ResolvedHost = config.resolve("other");
+        assert_eq!(other.connect_timeout, Some(20));
+        assert_eq!(other.command_timeout, None);
+        assert_eq!(other.transfer_timeout, None);
+    }
+}
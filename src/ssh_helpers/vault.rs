@@ -0,0 +1,488 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use argon2::Algorithm;
+use argon2::Argon2;
+use argon2::Params;
+use argon2::Version;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use chacha20poly1305::aead::Aead;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::ssh_helpers::ssh_directory;
+use crate::ssh_helpers::secret::Secret;
+
+// The first line of a vault file, changed if the format ever is. It's followed by the Argon2 costs
+// the key was derived with, like "trssh-vault 2 m=19456 t=2 p=1"
+const VAULT_HEADER: &str = "trssh-vault 2";
+
+// Version 1 files didn't record their costs, they were always the argon2 crate's 0.5 defaults
+const LEGACY_VAULT_HEADER: &str = "trssh-vault 1";
+
+// New vaults are made with OWASP's recommended Argon2id costs: 19 MiB, 2 passes, 1 lane
+const DEFAULT_KEY_COSTS: KeyCosts = KeyCosts { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 };
+
+// A damaged or tampered header shouldn't be able to make unlocking eat all the memory or take hours
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+
+// Locked again after this long without being used, unless --vault-timeout says otherwise
+pub const DEFAULT_VAULT_TIMEOUT_SECONDS: u64 = 300;
+
+pub fn default_vault_path() -> Option<PathBuf> {
+    ssh_directory().map(|dir| dir.join("trssh.vault"))
+}
+
+// One saved login, found again by the hostname it was saved under
+pub struct VaultEntry {
+    pub host: String,
+    pub username: String,
+    pub password: Secret,
+    pub private_key: String,
+    pub passphrase: Secret,
+}
+
+// How hard Argon2 worked deriving a vault's key, kept in its header so it can be derived again
+#[derive(Clone, Copy)]
+struct KeyCosts {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+// A vault's key and logins once its master password has been checked. Argon2 is slow on purpose,
+// so this is worked out away from the UI thread and handed to Vault::unlock
+pub struct UnlockedVault {
+    salt: [u8; SALT_LENGTH],
+    costs: KeyCosts,
+    key: Zeroizing<[u8; KEY_LENGTH]>,
+    entries: Vec<VaultEntry>,
+}
+
+impl UnlockedVault {
+    // A new empty vault, only written out the first time a login is saved to it
+    pub fn create(master_password: &Secret) -> Result<Self, String> {
+        if master_password.is_empty() {
+            return Err("[SSH VAULT] Type the vault's master password first".to_string());
+        }
+
+        let mut salt: [u8; SALT_LENGTH] = [0; SALT_LENGTH];
+        rand::rng().fill_bytes(&mut salt);
+
+        Ok(UnlockedVault {
+            salt,
+            costs: DEFAULT_KEY_COSTS,
+            key: derive_key(master_password, &salt, DEFAULT_KEY_COSTS)?,
+            entries: Vec::new(),
+        })
+    }
+
+    pub fn open(path: &Path, master_password: &Secret) -> Result<Self, String> {
+        if master_password.is_empty() {
+            return Err("[SSH VAULT] Type the vault's master password first".to_string());
+        }
+
+        // Creating one is left to create, so a mistyped password can't quietly become a new vault's
+        if !path.exists() {
+            return Err(format!("[SSH VAULT] There's no vault at {}", path.display()));
+        }
+
+        let contents: String = fs::read_to_string(path)
+            .map_err(|_| format!("[SSH VAULT] Could not read {}", path.display()))?;
+
+        let not_vault = || format!("[SSH VAULT] {} isn't a trssh vault, or is damaged", path.display());
+
+        let mut lines = contents.lines();
+
+        let costs: KeyCosts = lines.next()
+            .and_then(parse_header)
+            .ok_or_else(not_vault)?;
+
+        let mut fields: Vec<Vec<u8>> = Vec::new();
+
+        for line in lines.take(3) {
+            fields.push(STANDARD.decode(line.trim()).map_err(|_| not_vault())?);
+        }
+
+        let [salt, nonce, ciphertext] = <[Vec<u8>; 3]>::try_from(fields).map_err(|_| not_vault())?;
+
+        let salt: [u8; SALT_LENGTH] = salt.try_into().map_err(|_| not_vault())?;
+
+        if nonce.len() != NONCE_LENGTH {
+            return Err(not_vault());
+        }
+
+        let key: Zeroizing<[u8; KEY_LENGTH]> = derive_key(master_password, &salt, costs)?;
+
+        // A wrong password and a tampered file look the same to the AEAD
+        let plaintext: Zeroizing<Vec<u8>> = XChaCha20Poly1305::new(key.as_ref().into())
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map(Zeroizing::new)
+            .map_err(|_| "[SSH VAULT] Wrong master password, or the vault has been changed".to_string())?;
+
+        Ok(UnlockedVault {
+            salt,
+            costs,
+            key,
+            entries: parse_entries(&plaintext).ok_or_else(not_vault)?,
+        })
+    }
+}
+
+// Saved logins, encrypted with a key derived from the master password by Argon2. Nothing is
+// decrypted until it's unlocked, and locking forgets the key and every entry
+pub struct Vault {
+    path: PathBuf,
+    timeout: Duration,
+
+    salt: [u8; SALT_LENGTH],
+    costs: KeyCosts,
+    key: Option<Zeroizing<[u8; KEY_LENGTH]>>,
+    entries: Vec<VaultEntry>,
+    last_used: Instant,
+}
+
+impl Vault {
+    pub fn new(path: PathBuf) -> Self {
+        Vault {
+            path,
+            timeout: Duration::from_secs(DEFAULT_VAULT_TIMEOUT_SECONDS),
+            salt: [0; SALT_LENGTH],
+            costs: DEFAULT_KEY_COSTS,
+            key: None,
+            entries: Vec::new(),
+            last_used: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    // Zero turns the auto-lock off
+    pub fn is_idle(&self) -> bool {
+        self.is_unlocked() && !self.timeout.is_zero() && self.last_used.elapsed() > self.timeout
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Takes on the key and logins worked out by UnlockedVault::open, returning how many logins it holds
+    pub fn unlock(&mut self, unlocked: UnlockedVault) -> usize {
+        self.salt = unlocked.salt;
+        self.costs = unlocked.costs;
+        self.key = Some(unlocked.key);
+        self.entries = unlocked.entries;
+        self.last_used = Instant::now();
+
+        self.entries.len()
+    }
+
+    pub fn lock(&mut self) {
+        self.key = None;
+        self.entries.clear();
+    }
+
+    pub fn find(&mut self, host: &str) -> Option<&VaultEntry> {
+        if !self.is_unlocked() {
+            return None;
+        }
+
+        self.last_used = Instant::now();
+        self.entries.iter().find(|entry| entry.host == host)
+    }
+
+    // Replaces any login already saved for the same host, then writes the vault out again
+    pub fn remember(&mut self, entry: VaultEntry) -> Result<(), String> {
+        if !self.is_unlocked() {
+            return Err("[SSH VAULT] Unlock the vault before saving a login to it".to_string());
+        }
+
+        self.last_used = Instant::now();
+        self.entries.retain(|saved| saved.host != entry.host);
+        self.entries.push(entry);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(key) = &self.key else {
+            return Err("[SSH VAULT] Unlock the vault before saving a login to it".to_string());
+        };
+
+        let mut nonce: [u8; NONCE_LENGTH] = [0; NONCE_LENGTH];
+        rand::rng().fill_bytes(&mut nonce);
+
+        let plaintext: Zeroizing<String> = serialise_entries(&self.entries);
+        let ciphertext: Vec<u8> = XChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|_| "[SSH VAULT] Could not encrypt the vault".to_string())?;
+
+        let header: String = format!("{} m={} t={} p={}", VAULT_HEADER, self.costs.memory_kib, self.costs.iterations, self.costs.parallelism);
+        let contents: String = format!("{}\n{}\n{}\n{}\n", header, STANDARD.encode(self.salt), STANDARD.encode(nonce), STANDARD.encode(ciphertext));
+
+        // Written next to the vault and moved over it, so a failed write can't lose the old one
+        let temporary: PathBuf = self.path.with_extension("tmp");
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        options.open(&temporary)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|_| format!("[SSH VAULT] Could not write {}", self.path.display()))
+    }
+}
+
+// The costs a vault's header says its key was derived with, None if the header isn't a vault's
+fn parse_header(line: &str) -> Option<KeyCosts> {
+    if line == LEGACY_VAULT_HEADER {
+        return Some(DEFAULT_KEY_COSTS);
+    }
+
+    let (mut memory_kib, mut iterations, mut parallelism) = (None, None, None);
+
+    for field in line.strip_prefix(VAULT_HEADER)?.split_whitespace() {
+        match field.split_once('=')? {
+            ("m", value) => memory_kib = value.parse::<u32>().ok(),
+            ("t", value) => iterations = value.parse::<u32>().ok(),
+            ("p", value) => parallelism = value.parse::<u32>().ok(),
+            _ => return None,
+        }
+    }
+
+    Some(KeyCosts {
+        memory_kib: memory_kib.filter(|m| *m <= MAX_MEMORY_KIB)?,
+        iterations: iterations.filter(|t| *t <= MAX_ITERATIONS)?,
+        parallelism: parallelism?,
+    })
+}
+
+fn derive_key(master_password: &Secret, salt: &[u8], costs: KeyCosts) -> Result<Zeroizing<[u8; KEY_LENGTH]>, String> {
+    let mut key: Zeroizing<[u8; KEY_LENGTH]> = Zeroizing::new([0; KEY_LENGTH]);
+
+    let params: Params = Params::new(costs.memory_kib, costs.iterations, costs.parallelism, Some(KEY_LENGTH))
+        .map_err(|_| "[SSH VAULT] The vault's key costs aren't ones Argon2 accepts".to_string())?;
+
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(master_password.expose().as_bytes(), salt, key.as_mut())
+        .map_err(|_| "[SSH VAULT] Could not derive the vault key".to_string())?;
+
+    Ok(key)
+}
+
+// One login per line, each field base64'd so tabs and newlines in them can't break the format
+fn serialise_entries(entries: &[VaultEntry]) -> Zeroizing<String> {
+    let mut text: Zeroizing<String> = Zeroizing::new(String::new());
+
+    for entry in entries {
+        let fields: [&str; 5] = [&entry.host, &entry.username, entry.password.expose(), &entry.private_key, entry.passphrase.expose()];
+        let encoded: Vec<Zeroizing<String>> = fields.iter()
+            .map(|field| Zeroizing::new(STANDARD.encode(field)))
+            .collect();
+
+        for (i, field) in encoded.iter().enumerate() {
+            if i > 0 {
+                text.push('\t');
+            }
+            text.push_str(field);
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+fn parse_entries(plaintext: &[u8]) -> Option<Vec<VaultEntry>> {
+    let text: &str = std::str::from_utf8(plaintext).ok()?;
+    let mut entries: Vec<VaultEntry> = Vec::new();
+
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let mut fields: Vec<Zeroizing<String>> = Vec::new();
+
+        for field in line.split('\t') {
+            let decoded: Zeroizing<Vec<u8>> = Zeroizing::new(STANDARD.decode(field).ok()?);
+            fields.push(Zeroizing::new(String::from_utf8(decoded.to_vec()).ok()?));
+        }
+
+        let [host, username, password, private_key, passphrase] = <[Zeroizing<String>; 5]>::try_from(fields).ok()?;
+
+        entries.push(VaultEntry {
+            host: host.to_string(),
+            username: username.to_string(),
+            password: Secret::new(password.to_string()),
+            private_key: private_key.to_string(),
+            passphrase: Secret::new(passphrase.to_string()),
+        });
+    }
+
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_path(name: &str) -> PathBuf {
+        let path: PathBuf = std::env::temp_dir().join(format!("trssh-vault-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn password(text: &str) -> Secret {
+        Secret::new(text.to_string())
+    }
+
+    // Unlocks the vault the way the UI does, just without the thread
+    fn unlock(vault: &mut Vault, master_password: &str) -> Result<usize, String> {
+        UnlockedVault::open(vault.path(), &password(master_password)).map(|unlocked| vault.unlock(unlocked))
+    }
+
+    // A vault holding one login for example.com, saved with the master password "master"
+    fn saved_vault(name: &str) -> PathBuf {
+        let path: PathBuf = vault_path(name);
+        let mut vault = Vault::new(path.clone());
+
+        assert_eq!(vault.unlock(UnlockedVault::create(&password("master")).unwrap()), 0);
+        vault.remember(VaultEntry {
+            host: "example.com".to_string(),
+            username: "alice".to_string(),
+            password: password("tab\tand\nnewline"),
+            private_key: String::new(),
+            passphrase: Secret::default(),
+        }).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn round_trips_logins() {
+        let path: PathBuf = saved_vault("round-trip");
+        let mut vault = Vault::new(path.clone());
+
+        assert!(vault.find("example.com").is_none());
+        assert_eq!(unlock(&mut vault, "master"), Ok(1));
+
+        let entry: &VaultEntry = vault.find("example.com").unwrap();
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.password.expose(), "tab\tand\nnewline");
+        assert!(entry.passphrase.is_empty());
+
+        vault.lock();
+        assert!(!vault.is_unlocked());
+        assert!(vault.find("example.com").is_none());
+
+        let header: String = fs::read_to_string(&path).unwrap().lines().next().unwrap().to_string();
+        assert_eq!(header, "trssh-vault 2 m=19456 t=2 p=1");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_creates_vaults_when_asked() {
+        let path: PathBuf = vault_path("missing");
+        let mut vault = Vault::new(path.clone());
+
+        assert!(!vault.exists());
+        assert!(unlock(&mut vault, "master").is_err());
+        assert!(!vault.is_unlocked());
+        assert!(UnlockedVault::create(&password("")).is_err());
+
+        assert_eq!(vault.unlock(UnlockedVault::create(&password("master")).unwrap()), 0);
+        assert!(!vault.exists());
+    }
+
+    #[test]
+    fn rejects_the_wrong_password() {
+        let path: PathBuf = saved_vault("wrong-password");
+        let mut vault = Vault::new(path.clone());
+
+        assert!(unlock(&mut vault, "not the master").is_err());
+        assert!(!vault.is_unlocked());
+        assert!(unlock(&mut vault, "").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_vaults() {
+        let path: PathBuf = saved_vault("tampered");
+        let contents: String = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        let mut ciphertext: Vec<u8> = STANDARD.decode(lines[3]).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered_ciphertext: String = STANDARD.encode(ciphertext);
+
+        for tampered in [
+            format!("{}\n{}\n{}\n{}\n", lines[0], lines[1], lines[2], tampered_ciphertext),
+            format!("{}\n{}\n{}\n", lines[0], lines[1], lines[2]),
+            format!("trssh-vault 3\n{}\n{}\n{}\n", lines[1], lines[2], lines[3]),
+            format!("trssh-vault 2 m=19456 t=3 p=1\n{}\n{}\n{}\n", lines[1], lines[2], lines[3]),
+        ] {
+            fs::write(&path, tampered).unwrap();
+            assert!(UnlockedVault::open(&path, &password("master")).is_err());
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn opens_version_1_vaults() {
+        let path: PathBuf = saved_vault("legacy");
+        let contents: String = fs::read_to_string(&path).unwrap();
+        let (_, body) = contents.split_once('\n').unwrap();
+        fs::write(&path, format!("{}\n{}", LEGACY_VAULT_HEADER, body)).unwrap();
+
+        let mut vault = Vault::new(path.clone());
+        assert_eq!(unlock(&mut vault, "master"), Ok(1));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parses_headers() {
+        let costs: KeyCosts = parse_header("trssh-vault 2 m=65536 t=3 p=4").unwrap();
+        assert_eq!((costs.memory_kib, costs.iterations, costs.parallelism), (65536, 3, 4));
+
+        let legacy: KeyCosts = parse_header(LEGACY_VAULT_HEADER).unwrap();
+        assert_eq!(legacy.memory_kib, DEFAULT_KEY_COSTS.memory_kib);
+
+        for header in ["", "trssh-vault 2", "trssh-vault 2 m=1 t=2", "trssh-vault 2 m=x t=2 p=1", "trssh-vault 2 m=1 t=2 p=1 q=4",
+            &format!("trssh-vault 2 m={} t=2 p=1", MAX_MEMORY_KIB + 1), &format!("trssh-vault 2 m=19456 t={} p=1", MAX_ITERATIONS + 1)] {
+            assert!(parse_header(header).is_none(), "{} should be rejected", header);
+        }
+    }
+}
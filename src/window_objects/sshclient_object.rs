@@ -1,50 +1,127 @@
-use ssh2::Session;
-use std::io::prelude::*;
-
-use std::net::TcpStream;
 use std::collections::BTreeMap;
-use std::path::Path; 
-use std::fs::File;
+use std::time::Duration;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use macroquad::input::is_key_down;
+use macroquad::input::is_key_pressed;
+use macroquad::input::KeyCode;
+use macroquad::miniquad::window::clipboard_get;
 
 use crate::window_objects::window_object_center::NonInteractable;
 use crate::window_objects::window_object_center::OnlyInteractable;
 use crate::window_objects::window_object_center::HiddenObjectMethods;
+use crate::window_objects::logger_object::Logger;
 
 use crate::object_ids::LOGGER;
-// Custom Error codes used to display points of failure and handle errors in a more syntaxically
-// attractive manner
-pub enum HandshakeErrorCode {
-    TcpFail,
-    SessionFail,
-    HandshakeFail,
-    LoginAuthFail,
-    SessionAuthFail,
-}
-
+use crate::object_ids::COMMAND_BOX;
+use crate::object_ids::PROMPT_BOX;
+use crate::object_ids::JUMP_BOX;
+use crate::object_ids::FORWARD_BOX;
+use crate::object_ids::TUNNEL_LIST;
+use crate::object_ids::HOSTNAME_BOX;
+use crate::object_ids::USERNAME_BOX;
+use crate::object_ids::PASSWORD_BOX;
+use crate::object_ids::PUBLIC_KEY_BOX;
+use crate::object_ids::PRIVATE_KEY_BOX;
+use crate::object_ids::PASSPHRASE_BOX;
+
+use crate::ssh_helpers::session_worker::AuthMethod;
+use crate::ssh_helpers::session_worker::ConnectionSettings;
+use crate::ssh_helpers::session_worker::ForwardAction;
+use crate::ssh_helpers::session_worker::LoginRequest;
+use crate::ssh_helpers::session_worker::SessionEvent;
+use crate::ssh_helpers::session_worker::SessionJob;
+use crate::ssh_helpers::session_worker::WorkerHandle;
+use crate::ssh_helpers::keyboard_interactive::ServerPrompt;
+use crate::ssh_helpers::algorithms::AlgorithmPreferences;
+use crate::ssh_helpers::key_discovery::discover_identities;
+use crate::ssh_helpers::key_discovery::looks_like_private_key;
+use crate::ssh_helpers::secret::Secret;
+use crate::ssh_helpers::vault::UnlockedVault;
+use crate::ssh_helpers::vault::Vault;
+use crate::ssh_helpers::vault::VaultEntry;
+use crate::ssh_helpers::vault::default_vault_path;
+use crate::ssh_helpers::certificate::format_duration;
+
+// Shown in the prompt textbox when no server prompt is waiting for an answer
+pub const PROMPT_BOX_IDLE_TEXT: &str = "Server prompts appear here";
+
+// Shown in the command textbox until the remote working directory is known
+pub const COMMAND_BOX_IDLE_TEXT: &str = "Enter Command";
+
+// The end of the working directory is kept when it's too long for the command textbox
+const MAX_DIRECTORY_PROMPT_LENGTH: usize = 28;
+
+// Put in the private key box in place of a pasted key, which is kept out of sight
+const PASTED_KEY_TEXT: &str = "(pasted key)";
+
+// The UI's side of the SSH connection. The connection itself lives on a worker thread, this turns
+// button presses and textboxes into jobs for it and shows whatever it sends back
 pub struct SSHClient {
-    remote_server: String,
-    username: String,
-    // Password auth
-    password: String,
-
-    // SSH Key Auth
-    passphrase: String,
-    public_key: String,
-    private_key: String,
-
-    // These are used by ssh2
-    tcp_stream: Option<TcpStream>,
-    session: Option<Session>,
-
-    // This is a variable which is checked before this structure runs code - if the tcp stream
-    // fails, to prevent the whole program closing this will block it
+    worker: WorkerHandle,
+    settings: ConnectionSettings,
+
+    // One of the Trust Host / Reject Host buttons has been pressed
+    host_key_decision: Option<bool>,
+
+    // Set by the Reconnect, Logout and Cancel buttons
+    reconnect_requested: bool,
+    disconnect_requested: bool,
+    cancel_requested: bool,
+
+    // Set by the key buttons, which fill in the key boxes
+    key_pick_requested: bool,
+    key_paste_requested: bool,
+    // Which of the keys in ~/.ssh the next press picks
+    next_picked_key: usize,
+    // Sent instead of a path while the private key box still says PASTED_KEY_TEXT
+    pasted_key: Option<Secret>,
+
+    // Shared by every tab, so unlocking it once is enough
+    vault: Rc<RefCell<Vault>>,
+    // Set by the vault's textbox and buttons
+    vault_unlock_request: Option<Secret>,
+    vault_save_requested: bool,
+    vault_lock_requested: bool,
+    // The worker is still deriving the key for the last unlock
+    vault_unlocking: bool,
+    // The first of the two master passwords asked for before a new vault is made
+    new_vault_password: Option<Secret>,
+
+    // Set by the Agent Login button for the next login, or for every login by --agent
+    agent_requested: bool,
+    prefer_agent: bool,
+
+    // Only the active tab's session is drawn and gets the login fields, buttons and command box
+    active: bool,
+    just_activated: bool,
+
+    // While in the background the logger and tunnel list are kept here, and put back on screen when
+    // the tab is selected again. Along with whatever was half typed into the command box
+    log_store: Option<Logger>,
+    tunnel_store: Option<Logger>,
+    command_draft: String,
+
+    // A keyboard-interactive prompt waiting for an answer, kept until answered even in the background
+    prompt: Option<ServerPrompt>,
+
+    // Shown as the command textbox's prompt
+    remote_directory: Option<String>,
+
+    prompt_box_id: u32,
+    jump_box_id: u32,
+
+    forward_action: Option<ForwardAction>,
+    forward_box_id: u32,
+    tunnel_list_id: u32,
+
+    // Mirrors of the worker's state, updated as it reports them
     session_still_valid: bool,
-
     have_logged_in: bool,
-    
-    login_field_values: (u32, u32, u32, u32, u32, u32),
 
-    previous_commands: Vec<String>,
+    login_field_values: (u32, u32, u32, u32, u32, u32),
 
     logger_id: u32,
 }
@@ -52,31 +129,106 @@ pub struct SSHClient {
 impl SSHClient {
     pub fn new() -> Self {
         SSHClient {
-            remote_server: String::new(),
-            username: String::new(),
-            
-            // For username/password login
-            password: String::new(),
+            worker: WorkerHandle::spawn(),
+            settings: ConnectionSettings::new(),
+
+            host_key_decision: None,
+
+            reconnect_requested: false,
+            disconnect_requested: false,
+            cancel_requested: false,
+
+            key_pick_requested: false,
+            key_paste_requested: false,
+            next_picked_key: 0,
+            pasted_key: None,
+
+            vault: Rc::new(RefCell::new(Vault::new(default_vault_path().unwrap_or(PathBuf::from("trssh.vault"))))),
+            vault_unlock_request: None,
+            vault_save_requested: false,
+            vault_lock_requested: false,
+            vault_unlocking: false,
+            new_vault_password: None,
+
+            agent_requested: false,
+            prefer_agent: false,
+
+            active: true,
+            just_activated: false,
+
+            log_store: None,
+            tunnel_store: None,
+            command_draft: String::new(),
+
+            prompt: None,
+
+            remote_directory: None,
 
-            // For Key 
-            passphrase: String::new(),
-            public_key: String::new(),
-            private_key: String::new(),
+            prompt_box_id: 0,
+            jump_box_id: 0,
 
-            tcp_stream: None,
-            session: None,
+            forward_action: None,
+            forward_box_id: 0,
+            tunnel_list_id: 0,
 
             have_logged_in: false,
             session_still_valid: true,
 
             login_field_values: (0, 0, 0, 0, 0, 0),
 
-            previous_commands: Vec::<String>::new(),
-            
             logger_id: 0,
         }
     }
 
+    // A session for another tab, with the same settings as this one
+    pub fn new_tab(&self) -> Self {
+        let mut session = SSHClient::new();
+
+        session.active = false;
+        session.settings = self.settings.clone();
+        // --socks belongs to the first session, another would only fail to bind the same port
+        session.settings.socks_spec = None;
+        session.prefer_agent = self.prefer_agent;
+        session.vault = Rc::clone(&self.vault);
+        session.worker.send(SessionJob::Configure(session.settings.clone()));
+        session.init();
+
+        session
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // Takes this session's logs off the screen so another tab's can go there
+    pub fn deactivate(&mut self, none: &BTreeMap<u32, NonInteractable>) {
+        if let Some(NonInteractable::Logger(log_obj)) = none.get(&self.logger_id) {
+            self.log_store = Some(log_obj.clone());
+        }
+        if let Some(NonInteractable::Logger(list_obj)) = none.get(&self.tunnel_list_id) {
+            self.tunnel_store = Some(list_obj.clone());
+        }
+        self.active = false;
+    }
+
+    // Puts this session's logs back on screen, a new session starts with them empty
+    pub fn activate(&mut self, none: &mut BTreeMap<u32, NonInteractable>) {
+        if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
+            match self.log_store.take() {
+                Some(stored) => *log_obj = stored,
+                None => log_obj.clear_lines(),
+            }
+        }
+        if let Some(NonInteractable::Logger(list_obj)) = none.get_mut(&self.tunnel_list_id) {
+            match self.tunnel_store.take() {
+                Some(stored) => *list_obj = stored,
+                None => list_obj.clear_lines(),
+            }
+        }
+        self.active = true;
+        self.just_activated = true;
+    }
+
     pub fn get_login_status(&self) -> bool {
         self.have_logged_in
     }
@@ -84,242 +236,460 @@ impl SSHClient {
     pub fn update_login_field_values(&mut self, one: u32, two: u32, three: u32, four: u32, five: u32, six: u32) {
         self.login_field_values = (one, two, three, four, five, six);
     }
-   
+
     pub fn is_session_still_valid(&self) -> bool {
         self.session_still_valid
     }
 
-    pub fn make_ssh_handshake(&mut self, rs: String, un: String, pw: String, pb: String, pv: String, pp: String, key_instead_of_password: bool) -> Result<i8, HandshakeErrorCode> {
-        self.remote_server = rs;
-        self.username = un;
-        self.password = pw;
-        self.public_key = pb;
-        self.private_key = pv;
-        self.passphrase = pp;
-
-        // Create a TcpStream by passing in the ssh key without the username
-        let tcp_stream_attempt = TcpStream::connect(format!("{}:22", self.remote_server))
-            .map_err(|_| {
-                self.session_still_valid = false;
-                HandshakeErrorCode::TcpFail
-            })?;
-        
-        // Create a session value to hold the session
-        let mut session_attempt = Session::new()
-            .map_err(|_| {
-                self.session_still_valid = false;
-                HandshakeErrorCode::SessionFail
-            })?;
-
-        // Link the two
-        session_attempt.set_tcp_stream(tcp_stream_attempt.try_clone().map_err(|_| {
-            self.session_still_valid = false;
-            HandshakeErrorCode::TcpFail
-            })
-        ?);
-
-        // Attempt to handshake
-        match session_attempt.handshake() {
-            Ok(()) => {/* Can Continue */}
-            Err(_) => {
-                // The user may have entered an invalid hostname, so don't necessarily destroy the session validity yet
-                return Err(HandshakeErrorCode::HandshakeFail); 
+    pub fn set_host_key_decision(&mut self, trust: bool) {
+        self.host_key_decision = Some(trust);
+    }
+
+    pub fn request_reconnect(&mut self) {
+        self.reconnect_requested = true;
+    }
+
+    pub fn request_disconnect(&mut self) {
+        self.disconnect_requested = true;
+    }
+
+    pub fn request_cancel(&mut self) {
+        self.cancel_requested = true;
+    }
+
+    pub fn request_key_pick(&mut self) {
+        self.key_pick_requested = true;
+    }
+
+    pub fn request_key_paste(&mut self) {
+        self.key_paste_requested = true;
+    }
+
+    pub fn request_vault_unlock(&mut self, master_password: Secret) {
+        self.vault_unlock_request = Some(master_password);
+    }
+
+    pub fn request_vault_save(&mut self) {
+        self.vault_save_requested = true;
+    }
+
+    pub fn request_vault_lock(&mut self) {
+        self.vault_lock_requested = true;
+    }
+
+    pub fn request_agent_login(&mut self) {
+        self.agent_requested = true;
+    }
+
+    pub fn set_prefer_agent(&mut self, prefer: bool) {
+        self.prefer_agent = prefer;
+    }
+
+    pub fn request_forward_action(&mut self, action: ForwardAction) {
+        self.forward_action = Some(action);
+    }
+
+    // The output arrives in the logger once the worker has run it
+    pub fn request_command(&mut self, command: &str) {
+        self.worker.send(SessionJob::ExecuteCommand(command.to_string()));
+    }
+
+    pub fn request_download(&mut self, filename: &str) {
+        self.worker.send(SessionJob::DownloadFile(filename.to_string()));
+    }
+
+    pub fn request_upload(&mut self, filename: &str) {
+        self.worker.send(SessionJob::UploadFile(filename.to_string()));
+    }
+
+    pub fn request_directory_upload(&mut self, directories: Vec<String>, files: Vec<String>) {
+        self.worker.send(SessionJob::UploadDirectory(directories, files));
+    }
+
+    // Used when the SOCKS button is pressed with an empty forward box, and started automatically on login
+    pub fn set_socks_spec(&mut self, spec: String) {
+        self.settings.socks_spec = Some(spec);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_default_port(&mut self, port: u16) {
+        self.settings.default_port = Some(port);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        self.settings.config_path = Some(path);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.settings.connect_timeout = Some(timeout);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    // None lets commands run for as long as they like
+    // Zero lets commands run forever, even if the config gives a timeout
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.settings.command_timeout = Some(timeout);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_transfer_timeout(&mut self, timeout: Duration) {
+        self.settings.transfer_timeout = Some(timeout);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_shell_profile(&mut self, environment: Vec<String>, startup_commands: Vec<String>, shell_setup: Option<String>) {
+        self.settings.environment = environment;
+        self.settings.startup_commands = startup_commands;
+        self.settings.shell_setup = shell_setup;
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_algorithms(&mut self, algorithms: AlgorithmPreferences) {
+        self.settings.algorithms = algorithms;
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_certificate_path(&mut self, path: PathBuf) {
+        self.settings.certificate_path = Some(path);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_passphrase(&mut self, passphrase: Secret) {
+        self.settings.passphrase = Some(passphrase);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_askpass(&mut self, program: PathBuf) {
+        self.settings.askpass = Some(program);
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    // Replaces the vault every tab shares, so should be set before any other tabs are opened
+    pub fn set_vault_path(&mut self, path: PathBuf) {
+        *self.vault.borrow_mut() = Vault::new(path);
+    }
+
+    pub fn set_vault_timeout(&mut self, timeout: Duration) {
+        self.vault.borrow_mut().set_timeout(timeout);
+    }
+
+    pub fn set_keepalive_interval(&mut self, seconds: u32) {
+        self.settings.keepalive_interval = seconds;
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    pub fn set_auto_reconnect(&mut self, reconnect: bool) {
+        self.settings.auto_reconnect = reconnect;
+        self.worker.send(SessionJob::Configure(self.settings.clone()));
+    }
+
+    // Called by the prompt textbox, returns false if nothing was asking
+    pub fn answer_prompt(&mut self, text: &str) -> bool {
+        if self.prompt.take().is_none() {
+            return false;
+        }
+
+        self.worker.send(SessionJob::AnswerPrompt(text.to_string()));
+        true
+    }
+
+    // The logger on screen when active, the stored one otherwise
+    fn logger<'a>(&'a mut self, none: &'a mut BTreeMap<u32, NonInteractable>) -> Option<&'a mut Logger> {
+        if !self.active {
+            return self.log_store.as_mut();
+        }
+
+        match none.get_mut(&self.logger_id) {
+            Some(NonInteractable::Logger(log_obj)) => Some(log_obj),
+            _ => None,
+        }
+    }
+
+    fn tunnel_list<'a>(&'a mut self, none: &'a mut BTreeMap<u32, NonInteractable>) -> Option<&'a mut Logger> {
+        if !self.active {
+            return self.tunnel_store.as_mut();
+        }
+
+        match none.get_mut(&self.tunnel_list_id) {
+            Some(NonInteractable::Logger(list_obj)) => Some(list_obj),
+            _ => None,
+        }
+    }
+
+    // Puts the working directory in the command textbox, like a shell prompt
+    fn show_remote_directory(&self, only: &mut BTreeMap<u32, OnlyInteractable>) {
+        let prompt: String = match &self.remote_directory {
+            Some(directory) => {
+                let skip: usize = directory.chars().count().saturating_sub(MAX_DIRECTORY_PROMPT_LENGTH);
+
+                if skip > 0 {
+                    format!("...{} $", directory.chars().skip(skip + 3).collect::<String>())
+                } else {
+                    format!("{} $", directory)
+                }
             }
+            None => COMMAND_BOX_IDLE_TEXT.to_string(),
+        };
+
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&COMMAND_BOX) {
+            obj.set_default_text(prompt);
         }
-        
-        if key_instead_of_password {
-            let public_key = if self.public_key == "" {
-                None
-            } else {
-                Some(Path::new(&self.public_key))
-            };
+    }
 
-            let passphrase = if self.passphrase == "" {
-                None
+    // Each press moves on to the next key in ~/.ssh, along with its .pub if there is one
+    fn pick_next_key(&mut self, only: &mut BTreeMap<u32, OnlyInteractable>, none: &mut BTreeMap<u32, NonInteractable>) {
+        let identities: Vec<PathBuf> = discover_identities();
+
+        if identities.is_empty() {
+            if let Some(log_obj) = self.logger(none) {
+                log_obj.add_line("[SSH KEY] No private keys were found in ~/.ssh");
+            }
+            return;
+        }
+
+        let index: usize = self.next_picked_key % identities.len();
+        self.next_picked_key = index + 1;
+        self.pasted_key = None;
+
+        let private_key: &PathBuf = &identities[index];
+        let public_key: PathBuf = PathBuf::from(format!("{}.pub", private_key.display()));
+
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&PRIVATE_KEY_BOX) {
+            obj.force_set_text(private_key.display().to_string());
+        }
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&PUBLIC_KEY_BOX) {
+            if public_key.exists() {
+                obj.force_set_text(public_key.display().to_string());
             } else {
-                Some(self.password.as_str())
-            };
+                obj.force_clear_text();
+            }
+        }
 
-            match session_attempt.userauth_pubkey_file(&self.username, public_key, Path::new(&self.private_key), passphrase) {
-                Ok(()) => {
-                    if !session_attempt.authenticated() {
-                        self.session_still_valid = false;
-                        return Err(HandshakeErrorCode::SessionAuthFail);
-                    }
-                }
-                Err(_) => {
-                    return Err(HandshakeErrorCode::LoginAuthFail);
-                }
+        let line: String = format!("[SSH KEY] Picked {} ({} of {})", private_key.display(), index + 1, identities.len());
+
+        if let Some(log_obj) = self.logger(none) {
+            log_obj.add_line(&line);
+        }
+    }
+
+    // The key is only ever held in memory, the box just shows that one was pasted
+    fn paste_key(&mut self, only: &mut BTreeMap<u32, OnlyInteractable>, none: &mut BTreeMap<u32, NonInteractable>) {
+        // ssh2 can only log in with a key held in memory on unix
+        if cfg!(not(unix)) {
+            if let Some(log_obj) = self.logger(none) {
+                log_obj.add_line("[SSH] Pasted keys are not supported on this platform");
+                log_obj.add_line("[SSH HELP] ...Save the key to a file and give its path instead");
+            }
+            return;
+        }
+
+        let clipboard: String = clipboard_get().unwrap_or_default();
+
+        if !looks_like_private_key(&clipboard) {
+            if let Some(log_obj) = self.logger(none) {
+                log_obj.add_line("[SSH KEY] The clipboard doesn't hold a private key");
+                log_obj.add_line("[SSH HELP] ...Copy the whole key file, including its BEGIN and END lines");
             }
+            return;
+        }
+
+        self.pasted_key = Some(Secret::new(clipboard));
+
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&PRIVATE_KEY_BOX) {
+            obj.force_set_text(PASTED_KEY_TEXT.to_string());
+        }
+
+        if let Some(log_obj) = self.logger(none) {
+            log_obj.add_line("[SSH KEY] Using the private key from the clipboard for the next login");
+        }
+    }
+
+    // The key is derived on the worker's side, the result comes back as SessionEvent::VaultUnlocked.
+    // A vault that doesn't exist yet needs its master password typed twice before it's made
+    fn unlock_vault(&mut self, master_password: Secret, none: &mut BTreeMap<u32, NonInteractable>) {
+        let path: PathBuf = self.vault.borrow().path().to_path_buf();
+
+        let lines: Vec<String> = if master_password.is_empty() {
+            vec!["[SSH VAULT] Type the vault's master password first".to_string()]
+        } else if self.vault_unlocking {
+            vec!["[SSH VAULT] Still unlocking the vault".to_string()]
+        } else if self.vault.borrow().exists() {
+            self.new_vault_password = None;
+            self.vault_unlocking = true;
+            self.worker.send(SessionJob::UnlockVault(path.clone(), master_password));
+
+            vec![format!("[SSH VAULT] Unlocking {}...", path.display())]
         } else {
-            // Attempt to authenticate a login with password
-            match session_attempt.userauth_password(&self.username, &self.password) {
-                Ok(()) => {
-                    if !session_attempt.authenticated() {
-                        self.session_still_valid = false;
-                        return Err(HandshakeErrorCode::SessionAuthFail);
-                    }
+            match self.new_vault_password.take() {
+                None => {
+                    self.new_vault_password = Some(master_password);
+
+                    vec![
+                        format!("[SSH VAULT] There's no vault at {}, a new one will be made", path.display()),
+                        "[SSH HELP] ...Type the same master password again to create it".to_string(),
+                    ]
                 }
-                Err(_) => {
-                    return Err(HandshakeErrorCode::LoginAuthFail);
+                Some(first) if first.expose() == master_password.expose() => {
+                    self.vault_unlocking = true;
+                    self.worker.send(SessionJob::CreateVault(master_password));
+
+                    vec![format!("[SSH VAULT] Creating a new vault at {}...", path.display())]
                 }
+                Some(_) => vec![
+                    "[SSH VAULT] The master passwords didn't match, no vault was made".to_string(),
+                    "[SSH HELP] ...Type the new master password twice to try again".to_string(),
+                ],
+            }
+        };
+
+        if let Some(log_obj) = self.logger(none) {
+            for line in lines {
+                log_obj.add_line(&line);
             }
         }
-        self.session = Some(session_attempt);
-        self.tcp_stream = Some(tcp_stream_attempt);
-        self.have_logged_in = true;
-        Ok(1)
-    }
-
-    // Returns Filename with directory if applicable, or error message
-    pub fn download_file(&mut self, filename: &str, directory: &str) -> Result<String, String> { 
-        let current_session = self.session
-            .clone()
-            .unwrap();
-
-        // Attempt to create a SFTP session
-        let sftp_session = current_session.sftp()
-            .map_err(|_| {
-                self.session_still_valid = false;
-                "[SSH ERROR] Error establishing an SFTP session".to_string()
-            })?;
-
-        // Directory is aquired through 'pwd' which has a \n at the end
-        let target_file_name: String = format!("{}/{}", directory.trim_matches('\n'), filename);
-
-        // Debug info for terminal
-        println!("Downloading {}", target_file_name);
-
-        // Open the file 
-        let mut target_file = sftp_session.open(Path::new(&target_file_name))
-            .map_err(|_| "[SSH WARN] Problem creating file link".to_string())?;
-
-        // Read contents into vector of strings
-        let mut downloaded_content = Vec::<u8>::new();
-
-        target_file.read_to_end(&mut downloaded_content)
-            .map_err(|_| "[SSH WARN] There was a problem trying to download the file contents")?;
-
-        // Save the contents into the desired file
-        std::fs::write(&filename, downloaded_content)
-            .map_err(|_| "[SSH WARN] Problem creating a local save file to store the data in")?;
-
-        Ok(filename.to_string())
-    }
-
-    pub fn upload_file(&mut self, filename: &str, directory: &str) -> Result<String, String> {
-        let current_session = self.session
-            .clone()
-            .unwrap();
-    
-        let sftp_session = current_session.sftp()
-            .map_err(|_| {
-                self.session_still_valid = false;
-                "[SSH ERROR] Error establishing an SFTP session".to_string()
-            })?;
-
-        let target_destination = format!("{}/{}", directory.trim_matches('\n'), filename);
-
-        let mut local_file = File::open(filename)
-            .map_err(|_| "[SSH WARN] There was a problem finding the file to upload".to_string())?;
-        let mut file_contents = Vec::new();
-        
-        local_file.read_to_end(&mut file_contents)
-            .map_err(|_| "[SSH WARN] There was a problem reading the file to upload".to_string())?;
-    
-        // Now create the file in the remote server
-        let mut target_file = sftp_session.create(Path::new(&target_destination))
-            .map_err(|_| {
-                "[SSH WARN] Could not create file link in destination folder"
-            })?;
-
-        target_file.write_all(&file_contents)
-            .map_err(|_| "[SSH WARN] Could not write data to target file")?;
-
-        Ok(target_destination)
-    }
-
-    pub fn execute_command(&mut self, new_command: &str, add_to_command_list: bool) -> Result<Vec<String>, String> {
-        let mut current_channel = self.session
-            .clone()
-            .unwrap()
-            .channel_session();
-        
-        let mut full_command: String = "source ~/.bashrc".to_string(); 
-        let mut resulting_lines: Vec<String> = Vec::<String>::new();     
-        
-        // Filter only commands that contain cd
-        for com in &self.previous_commands {
-            if com.contains("cd ") {
-                full_command = format!("{}; {}", full_command, com);
+    }
+
+    fn finish_unlocking_vault(&mut self, result: Result<UnlockedVault, String>, none: &mut BTreeMap<u32, NonInteractable>) {
+        self.vault_unlocking = false;
+
+        let path: String = self.vault.borrow().path().display().to_string();
+
+        let line: String = match result.map(|unlocked| self.vault.borrow_mut().unlock(unlocked)) {
+            Ok(0) => format!("[SSH VAULT] Unlocked {}, it has no saved logins yet", path),
+            Ok(count) => format!("[SSH VAULT] Unlocked {}, it has {} saved logins", path, count),
+            Err(message) => message,
+        };
+
+        if let Some(log_obj) = self.logger(none) {
+            log_obj.add_line(&line);
+        }
+    }
+
+    // Saves what's in the login boxes under the hostname, without logging in or clearing them
+    fn save_to_vault(&mut self, only: &mut BTreeMap<u32, OnlyInteractable>, none: &mut BTreeMap<u32, NonInteractable>) {
+        let mut fields: [String; 5] = [const {String::new()}; 5];
+
+        for (i, id) in [HOSTNAME_BOX, USERNAME_BOX, PASSWORD_BOX, PRIVATE_KEY_BOX, PASSPHRASE_BOX].iter().enumerate() {
+            if let Some(OnlyInteractable::TextBox(obj)) = only.get(id) {
+                fields[i] = obj.get_text();
             }
         }
 
-        // If the new command is cd / then clear command history   
-        if new_command.contains("cd /") {
-            full_command = new_command.to_string();
-            self.previous_commands = Vec::new();
+        let [host, username, password, private_key, passphrase] = fields;
+
+        let result: Result<String, String> = if host.is_empty() {
+            Err("[SSH VAULT] Type the hostname the login is for before saving it".to_string())
+        } else if private_key == PASTED_KEY_TEXT {
+            Err("[SSH VAULT] Pasted keys aren't saved to the vault, save the key's path instead".to_string())
+        // Logging in refuses a password alongside a key, so such a login could never be used
+        } else if !password.is_empty() && (!private_key.is_empty() || !passphrase.is_empty()) {
+            Err("[SSH VAULT] Save either a password or a key with its passphrase, not both".to_string())
         } else {
-            full_command = format!("{}; {}", full_command, new_command);
+            let line: String = format!("[SSH VAULT] Saved the login for {}", host);
+
+            self.vault.borrow_mut().remember(VaultEntry {
+                host,
+                username,
+                password: Secret::new(password),
+                private_key,
+                passphrase: Secret::new(passphrase),
+            }).map(|_| line)
+        };
+
+        if let Some(log_obj) = self.logger(none) {
+            log_obj.add_line(&result.unwrap_or_else(|message| message));
+        }
+    }
+
+    // Fills in a login that only has a hostname from the vault, if it's unlocked and has one saved
+    fn fill_from_vault(&mut self, contents: &mut [String; 6], none: &mut BTreeMap<u32, NonInteractable>) {
+        if contents[0].is_empty() || contents[2..].iter().any(|field| !field.is_empty()) {
+            return;
         }
 
-        let command: &str = &full_command;
+        let filled: bool = match self.vault.borrow_mut().find(&contents[0]) {
+            Some(entry) => {
+                if contents[1].is_empty() {
+                    contents[1] = entry.username.clone();
+                }
+                contents[2] = entry.password.expose().to_string();
+                contents[4] = entry.private_key.clone();
+                contents[5] = entry.passphrase.expose().to_string();
+                true
+            }
+            None => false,
+        };
+
+        if filled && let Some(log_obj) = self.logger(none) {
+            log_obj.add_line(&format!("[SSH VAULT] Using the saved login for {}", contents[0]));
+        }
+    }
+
+    fn show_prompt(&self, only: &mut BTreeMap<u32, OnlyInteractable>) {
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&self.prompt_box_id) {
+            obj.force_clear_text();
+
+            match &self.prompt {
+                Some(prompt) => {
+                    obj.set_password_mode(!prompt.echo);
+                    obj.set_default_text(prompt.text.trim().to_string());
+                }
+                None => {
+                    obj.set_password_mode(true);
+                    obj.set_default_text(PROMPT_BOX_IDLE_TEXT.to_string());
+                }
+            }
+        }
+    }
 
-        println!("Executing command: {}", command);
+    // Shows everything the worker has sent since the last frame
+    fn drain_worker_events(&mut self, only: &mut BTreeMap<u32, OnlyInteractable>, none: &mut BTreeMap<u32, NonInteractable>) {
+        for event in self.worker.drain_events() {
+            match event {
+                SessionEvent::Log(line) => {
+                    if let Some(log_obj) = self.logger(none) {
+                        log_obj.add_line(&line);
+                    }
+                }
+                SessionEvent::Status { logged_in, still_valid } => {
+                    self.have_logged_in = logged_in;
+                    self.session_still_valid = still_valid;
+                }
+                SessionEvent::Prompt(prompt) => {
+                    self.prompt = Some(prompt);
 
-        // Return type Result<Session, Error>
-        match current_channel {
-            Ok(ref mut channel) => {
-                // exec has return type Result<(), Error>
-                match channel.exec(command) {
-                    Ok(()) => { /* Good! We can continue */}
-                    Err(_) => {
-                        self.session_still_valid = false;
-                        return Err("[SSH ERROR] There was an error executing a command.".to_string());
+                    if self.active {
+                        self.show_prompt(only);
                     }
                 }
+                SessionEvent::PromptsFinished => {
+                    self.prompt = None;
 
-                let mut result = String::new(); 
-                let mut error_result = String::new();
-                // This has a Result<usize, Err> where usize is the number of bytes
-
-                // Read the result from the channel
-                channel.read_to_string(&mut result)
-                    .map_err(|_| {
-                        self.session_still_valid = false;
-                        "[SSH ERROR] The channel was unable to read the result of your command.".to_string()
-                    })?;
-
-                // Read any error from the channel
-                channel.stderr().read_to_string(&mut error_result)
-                    .map_err(|_| {
-                        self.session_still_valid = false;
-                        "[SSH ERROR] The channel was unable to read the error result of your command.".to_string()
-                    })?;
-                
-                // Let the channel gracefully close
-                channel.wait_close()
-                    .map_err(|_| {
-                        self.session_still_valid = false;
-                        "[SSH ERROR] The channel was unable to gracefully close.".to_string()
-                    })?;
-                
-                if !error_result.is_empty() {
-                    resulting_lines.push(format!("Command Failed, Error: {}", error_result));
-                } else {
-                    resulting_lines.push(result);
+                    if self.active {
+                        self.show_prompt(only);
+                    }
+                }
+                SessionEvent::WorkingDirectory(directory) => {
+                    self.remote_directory = directory;
 
-                    // Now check if it needs adding to the command list
-                    if add_to_command_list {
-                        self.previous_commands.push(new_command.to_string());
+                    if self.active {
+                        self.show_remote_directory(only);
                     }
-                } 
+                }
+                SessionEvent::VaultUnlocked(result) => {
+                    self.finish_unlocking_vault(result, none);
+                }
+                SessionEvent::Tunnels(lines) => {
+                    if let Some(list_obj) = self.tunnel_list(none) {
+                        list_obj.clear_lines();
 
-                Ok(resulting_lines)
-            }
-            Err(_) => {
-                self.session_still_valid = false;
-                Err("[SSH ERROR] There was an error establishing a session-based channel.".to_string())
+                        for line in lines {
+                            list_obj.add_line(&line);
+                        }
+                    }
+                }
             }
         }
     }
@@ -327,97 +697,203 @@ impl SSHClient {
 
 impl HiddenObjectMethods for SSHClient {
     fn init(&mut self) {
-        self.logger_id = LOGGER; 
+        self.logger_id = LOGGER;
+        self.prompt_box_id = PROMPT_BOX;
+        self.jump_box_id = JUMP_BOX;
+        self.forward_box_id = FORWARD_BOX;
+        self.tunnel_list_id = TUNNEL_LIST;
     }
 
     fn update(&mut self, only: &mut BTreeMap<u32, OnlyInteractable>, none: &mut BTreeMap<u32, NonInteractable>) {
-        if self.session_still_valid {
-            // If these values have been changed, it means a 'login' button has been filled in
-            if  self.login_field_values.0 != 0       // Hostname
-                 && self.login_field_values.1 != 0   // Username
-                 && self.login_field_values.2 != 0   // Password 
-                 && self.login_field_values.3 != 0   // (OR) Public Key
-                 && self.login_field_values.4 != 0   // Private key
-                 && self.login_field_values.5 != 0 { // Passphrase
-
-                // First, get the values from the text boxes, using the login_field_values as IDs           
-                let mut contents: [String; 6] = [const {String::new()}; 6];
-
-                for (i, id) in [self.login_field_values.0, 
-                    self.login_field_values.1, 
-                    self.login_field_values.2, 
-                    self.login_field_values.3, 
-                    self.login_field_values.4, 
-                    self.login_field_values.5].iter().enumerate() {
-                    
-                    if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(id) {
-                        contents[i] = obj.get_text().to_string();
-                        obj.force_clear_text();
-                    }
+        // Results keep arriving even after the session has failed, so the failure itself is shown
+        self.drain_worker_events(only, none);
+
+        // Everything after this reads the shared textboxes, which belong to the active tab
+        if !self.active {
+            return;
+        }
+
+        // The shared boxes still show the last tab's prompt and command
+        if self.just_activated {
+            self.just_activated = false;
+            self.show_prompt(only);
+            self.show_remote_directory(only);
+
+            if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&COMMAND_BOX) {
+                obj.force_set_text(self.command_draft.clone());
+            }
+        } else if let Some(OnlyInteractable::TextBox(obj)) = only.get(&COMMAND_BOX) {
+            self.command_draft = obj.get_text();
+        }
+
+        // Sent even after the connection drops, the worker decides what each request can still do
+        if self.reconnect_requested {
+            self.reconnect_requested = false;
+            self.worker.send(SessionJob::Reconnect);
+        }
+
+        if self.disconnect_requested {
+            self.disconnect_requested = false;
+            self.worker.send(SessionJob::Disconnect);
+        }
+
+        // Ctrl+C in the command box works like it would in a terminal
+        if let Some(OnlyInteractable::TextBox(obj)) = only.get(&COMMAND_BOX)
+            && obj.get_pressed_down()
+            && (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
+            && is_key_pressed(KeyCode::C) {
+            self.cancel_requested = true;
+        }
+
+        if self.cancel_requested {
+            self.cancel_requested = false;
+            self.worker.send(SessionJob::CancelCommand);
+        }
+
+        if self.key_pick_requested {
+            self.key_pick_requested = false;
+            self.pick_next_key(only, none);
+        }
+
+        if self.key_paste_requested {
+            self.key_paste_requested = false;
+            self.paste_key(only, none);
+        }
+
+        if let Some(master_password) = self.vault_unlock_request.take() {
+            self.unlock_vault(master_password, none);
+        }
+
+        if self.vault_save_requested {
+            self.vault_save_requested = false;
+            self.save_to_vault(only, none);
+        }
+
+        // Locked by the button, or by going unused for too long
+        let idle: bool = self.vault.borrow().is_idle();
+
+        if self.vault_lock_requested || idle {
+            self.vault_lock_requested = false;
+
+            let was_unlocked: bool = self.vault.borrow().is_unlocked();
+            let timeout: Duration = self.vault.borrow().timeout();
+            self.vault.borrow_mut().lock();
+
+            let line: String = if idle {
+                format!("[SSH VAULT] Locked after {} without being used", format_duration(timeout.as_secs()))
+            } else if was_unlocked {
+                "[SSH VAULT] Locked".to_string()
+            } else {
+                "[SSH VAULT] The vault is already locked".to_string()
+            };
+
+            if let Some(log_obj) = self.logger(none) {
+                log_obj.add_line(&line);
+            }
+        }
+
+        if let Some(trust) = self.host_key_decision.take() {
+            self.worker.send(SessionJob::HostKeyDecision(trust));
+        }
+
+        if let Some(action) = self.forward_action.take() {
+            let mut spec: String = String::new();
+
+            if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&self.forward_box_id) {
+                spec = obj.get_text();
+                obj.force_clear_text();
+            }
+
+            self.worker.send(SessionJob::Forward(action, spec));
+        }
+
+        // If these values have been changed, it means a 'login' button has been filled in
+        if  self.login_field_values.0 != 0       // Hostname
+             && self.login_field_values.1 != 0   // Username
+             && self.login_field_values.2 != 0   // Password
+             && self.login_field_values.3 != 0   // (OR) Public Key
+             && self.login_field_values.4 != 0   // Private key
+             && self.login_field_values.5 != 0 { // Passphrase
+
+            // First, get the values from the text boxes, using the login_field_values as IDs
+            let mut contents: [String; 6] = [const {String::new()}; 6];
+
+            for (i, id) in [self.login_field_values.0,
+                self.login_field_values.1,
+                self.login_field_values.2,
+                self.login_field_values.3,
+                self.login_field_values.4,
+                self.login_field_values.5].iter().enumerate() {
+
+                if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(id) {
+                    contents[i] = obj.get_text();
+                    obj.force_clear_text();
                 }
+            }
 
-                // Hostname and Username are mandatory so if they arent here throw this warning
-                if contents[0] == "" || contents[1] == "" {
-                    if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
-                        log_obj.add_line("[SSH CONNECT] There is a missing piece of info before attempting to log in.");
-                    }
-                // If there is contents in the password field and any field relating to SSHing, throw this warning 
-                } else if contents[2] != "" && (contents[3] != "" || contents[4] != "" || contents[5] != "") {
-                    if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
-                        log_obj.add_line("[SSH CONNECT] Please SSH using only SSH Keys or by password, not both.");
-                    }
-                // Otherwise, its okay
+            self.fill_from_vault(&mut contents, none);
+
+            // The jump host field isn't one of the six, but is part of the same login
+            let mut jump_hosts: String = String::new();
+
+            if let Some(OnlyInteractable::TextBox(obj)) = only.get_mut(&self.jump_box_id) {
+                jump_hosts = obj.get_text();
+                obj.force_clear_text();
+            }
+
+            // Hostname is mandatory so if it isnt here throw this warning. The username can also
+            // come from user@host in the hostname field or from ~/.ssh/config
+            if contents[0] == "" {
+                if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
+                    log_obj.add_line("[SSH CONNECT] There is a missing piece of info before attempting to log in.");
+                }
+            // If there is contents in the password field and any field relating to SSHing, throw this warning
+            } else if contents[2] != "" && (contents[3] != "" || contents[4] != "" || contents[5] != "") {
+                if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
+                    log_obj.add_line("[SSH CONNECT] Please SSH using only SSH Keys or by password, not both.");
+                }
+            // Otherwise, its okay
+            } else {
+                // So the handshake method knows which auth method to use
+                let auth_method: AuthMethod = if self.agent_requested || self.prefer_agent {
+                    AuthMethod::Agent
+                } else if contents[4] != "" {
+                    AuthMethod::KeyFile
                 } else {
-                    // Boolean value so the handshake method knows which auth method to use
-                    let key_instead_of_password: bool = if contents[4] != "" {
-                        true
-                    } else {
-                        false
-                    };
-
-                    // Now attempt handshake
-                    let ssh_result: Result<i8, HandshakeErrorCode> = self.make_ssh_handshake(
-                                                            contents[0].clone(), 
-                                                            contents[1].clone(), 
-                                                            contents[2].clone(), 
-                                                            contents[3].clone(), 
-                                                            contents[4].clone(), 
-                                                            contents[5].clone(), 
-                                                            key_instead_of_password
-                                                         );
-                    
-                    if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
-                        match ssh_result {
-                            Ok(_) => {
-                               log_obj.add_line(&format!("[SSH INFO] Successful SSH into {}", self.remote_server)); 
-                            }
-                            Err(err_code) => {
-                                match err_code {
-                                    HandshakeErrorCode::TcpFail => {
-                                        log_obj.add_line("[SSH ERROR] Failed to establish a TCP Connection");
-                                    }
-                                    HandshakeErrorCode::SessionFail => {
-                                        log_obj.add_line("[SSH ERROR] Failed to establish a new session");
-                                    }
-                                    HandshakeErrorCode::HandshakeFail => {
-                                        log_obj.add_line("[SSH WARN] Failed to create a link between a TCP Connection and a Session");
-                                        log_obj.add_line("[SSH HELP] ...Did you perhaps misspell the hostname?");
-                                    }
-                                    HandshakeErrorCode::LoginAuthFail => {
-                                        log_obj.add_line("[SSH WARN] Failed to authenticate a login");
-                                        log_obj.add_line("[SSH HELP] ...Did you type your username and password correctly?");
-                                    }
-                                    HandshakeErrorCode::SessionAuthFail => {
-                                        log_obj.add_line("[SSH ERROR] Failed to authenticate a Session");
-                                    }
-                                } 
-                            }
-                        }
-                    }
-                }    
-                // Set back to zero so it doesn't endlessly occur
-                self.login_field_values = (0,0,0,0,0,0);
-             }
-        }
+                    AuthMethod::Password
+                };
+
+                if let Some(NonInteractable::Logger(log_obj)) = none.get_mut(&self.logger_id) {
+                    log_obj.add_line(&format!("[SSH CONNECT] Connecting to {}...", contents[0]));
+                }
+
+                // The worker logs the result once the handshake is done
+                let [hostname, username, password, public_key, mut private_key, passphrase] = contents;
+
+                let private_key_data: Option<Secret> = if private_key == PASTED_KEY_TEXT {
+                    private_key = String::new();
+                    self.pasted_key.take()
+                } else {
+                    None
+                };
+
+                self.worker.send(SessionJob::Login(LoginRequest {
+                    hostname,
+                    username,
+                    password: Secret::new(password),
+                    public_key,
+                    private_key,
+                    passphrase: Secret::new(passphrase),
+                    private_key_data,
+                    jump_hosts,
+                    auth_method,
+                }));
+            }
+            // Set back to zero so it doesn't endlessly occur
+            self.login_field_values = (0,0,0,0,0,0);
+            self.agent_requested = false;
+            // The private key box was cleared along with the pasted key's placeholder
+            self.pasted_key = None;
+         }
     }
 }
@@ -114,6 +114,16 @@ impl TextBox {
     pub fn force_set_text(&mut self, text: String) {
         self.text_container.set_text(text);
     }
+
+    // For textboxes whose purpose changes while the program runs, like answering server prompts
+    pub fn set_default_text(&mut self, text: String) {
+        self.default_text = text;
+    }
+
+    pub fn set_password_mode(&mut self, pm: bool) {
+        self.password_mode = pm;
+        self.text_container.set_password_mode(pm);
+    }
 }
 
 
@@ -132,10 +142,14 @@ impl WindowObjectMethods for TextBox {
                 // Only one key per frame
                 let down_key: Option<char> = get_char_pressed();
                     
+                // Ctrl shortcuts like Ctrl+C aren't typing
+                let ctrl_down: bool = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+
                 if let Some(character) = down_key {
                     // If it is valid and wasn't the character on the previous frame
                     if ALLOWED_CHARACTERS.contains(&character.to_string()) 
-                        && character != self.previous_char {
+                        && character != self.previous_char
+                        && !ctrl_down {
                         let mut current: String = self.text_container.get_text();
 
                         current.push(character);